@@ -0,0 +1,89 @@
+use chrono::NaiveDate;
+use crate::{options::OptionRight, strategies::OptionQuote};
+
+/// Expected move of the underlying by expiration, taken as the mid price of the at-the-money straddle.
+/// Returns None if the quotes aren't a call and put at the same strike and expiration, or either has no market.
+pub fn expected_move(call:&OptionQuote, put:&OptionQuote) -> Option<f64> {
+    if call.spec.right != OptionRight::Call || put.spec.right != OptionRight::Put
+        || call.spec.strike != put.spec.strike || call.spec.expiration != put.spec.expiration {
+        return None;
+    }
+    let mid = |q:&OptionQuote| (q.bid > 0.0 && q.ask >= q.bid).then(|| (q.bid + q.ask) / 2.0);
+    Some(mid(call)? + mid(put)?)
+}
+
+/// Picks the at-the-money call and put from a chain: the strike closest to the underlying price that has both.
+pub fn atm_straddle(chain:&[OptionQuote], underlying_price:f64, expiration:NaiveDate) -> Option<(&OptionQuote, &OptionQuote)> {
+    let find = |right:OptionRight, strike:f64| chain.iter().find(|q| q.spec.right == right && q.spec.strike == strike && q.spec.expiration == expiration);
+    chain.iter()
+        .filter(|q| q.spec.expiration == expiration && q.spec.right == OptionRight::Call)
+        .filter_map(|call| Some((call, find(OptionRight::Put, call.spec.strike)?)))
+        .min_by(|(a, _), (b, _)| (a.spec.strike - underlying_price).abs().total_cmp(&(b.spec.strike - underlying_price).abs()))
+}
+
+/// Rough probability of finishing in the money from the option's delta, as traders commonly use it.
+pub fn probability_itm_from_delta(delta:f64) -> f64 {
+    delta.abs().min(1.0)
+}
+
+/// Probability of finishing in the money under Black-Scholes: N(d2) for calls and N(-d2) for puts.
+/// volatility and rate are annualized decimals (0.2 for 20%), years is the time to expiration.
+pub fn probability_itm(right:OptionRight, strike:f64, underlying_price:f64, volatility:f64, years:f64, rate:f64) -> Option<f64> {
+    if strike <= 0.0 || underlying_price <= 0.0 || volatility <= 0.0 || years <= 0.0 {
+        return None;
+    }
+    let vol_sqrt_t = volatility * years.sqrt();
+    let d2 = ((underlying_price / strike).ln() + (rate - volatility * volatility / 2.0) * years) / vol_sqrt_t;
+    Some(match right {
+        OptionRight::Call => normal_cdf(d2),
+        OptionRight::Put => normal_cdf(-d2),
+    })
+}
+
+/// Standard normal CDF using the Abramowitz and Stegun 7.1.26 erf approximation, accurate to about 1e-7.
+pub fn normal_cdf(x:f64) -> f64 {
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * z);
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    if x >= 0.0 { (1.0 + erf) / 2.0 } else { (1.0 - erf) / 2.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::OptionSpec;
+
+    fn quote(right:OptionRight, strike:f64, bid:f64, ask:f64) -> OptionQuote {
+        OptionQuote::new(OptionSpec::new("SPY", NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(), right, strike), bid, ask)
+    }
+
+    #[test]
+    fn test_expected_move() {
+        let chain = [
+            quote(OptionRight::Call, 495.0, 7.0, 7.2), quote(OptionRight::Put, 495.0, 2.0, 2.2),
+            quote(OptionRight::Call, 500.0, 4.0, 4.2), quote(OptionRight::Put, 500.0, 3.8, 4.0),
+            quote(OptionRight::Call, 505.0, 2.0, 2.1),
+        ];
+        let (call, put) = atm_straddle(&chain, 503.0, NaiveDate::from_ymd_opt(2024, 6, 21).unwrap()).unwrap();
+        assert_eq!(call.spec.strike, 500.0);
+        assert!((expected_move(call, put).unwrap() - 8.0).abs() < 1e-9);
+        assert_eq!(expected_move(&chain[0], &chain[3]), None);
+        assert_eq!(expected_move(&chain[2], &quote(OptionRight::Put, 500.0, 0.0, 0.1)), None);
+    }
+
+    #[test]
+    fn test_probability_itm() {
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-7);
+        assert!((normal_cdf(1.96) - 0.9750021).abs() < 1e-6);
+        assert!((normal_cdf(-1.0) - 0.1586553).abs() < 1e-6);
+
+        let call = probability_itm(OptionRight::Call, 100.0, 100.0, 0.2, 1.0, 0.0).unwrap();
+        let put = probability_itm(OptionRight::Put, 100.0, 100.0, 0.2, 1.0, 0.0).unwrap();
+        // d2 = -0.1
+        assert!((call - 0.4601722).abs() < 1e-6);
+        assert!((call + put - 1.0).abs() < 1e-9);
+        assert_eq!(probability_itm(OptionRight::Call, 100.0, 100.0, 0.2, 0.0, 0.0), None);
+        assert_eq!(probability_itm_from_delta(-0.3), 0.3);
+    }
+}
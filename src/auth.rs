@@ -0,0 +1,283 @@
+use std::{env, fmt, fs, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex as StdMutex}, time::{Duration, Instant, SystemTime}};
+use futures_util::future::BoxFuture;
+use reqwest::Url;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use crate::{error::Error, http::{send_request, shared_client}};
+
+// See: https://documentation.tradier.com/brokerage-api/oauth/authorization-code
+pub const AUTHORIZE_URL: &str = "https://api.tradier.com/v1/oauth/authorize";
+const ACCESS_TOKEN_URL: &str = "https://api.tradier.com/v1/oauth/accesstoken";
+const REFRESH_TOKEN_URL: &str = "https://api.tradier.com/v1/oauth/refreshtoken";
+
+/// Tokens are refreshed this long before they expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Supplies the bearer token used for API requests.
+pub trait TokenProvider: Send + Sync {
+    fn token(&self) -> BoxFuture<'_, Result<String, Error>>;
+
+    /// Called when a request made with the current token was rejected as unauthorized,
+    /// so the next call to token should get a fresh one if it can.
+    fn invalidate(&self) {}
+}
+
+/// Async function returning the current token, see [`Credentials::Callback`].
+pub type TokenCallback = Arc<dyn Fn() -> BoxFuture<'static, Result<String, Error>> + Send + Sync>;
+
+/// Where the API token comes from. Anything other than Static is looked up per request so tokens can be rotated
+/// without restarting.
+#[derive(Clone)]
+pub enum Credentials {
+    Static(String),
+    /// Name of an environment variable holding the token.
+    Env(String),
+    /// File containing the token, reloaded when its modified time changes.
+    File(Arc<TokenFile>),
+    Callback(TokenCallback),
+}
+
+impl Credentials {
+    pub fn file<P:Into<PathBuf>>(path:P) -> Self {
+        Credentials::File(Arc::new(TokenFile { path: path.into(), cached: StdMutex::new(None) }))
+    }
+
+    pub fn callback<F>(f:F) -> Self where F:Fn() -> BoxFuture<'static, Result<String, Error>> + Send + Sync + 'static {
+        Credentials::Callback(Arc::new(f))
+    }
+}
+
+/// The TRADIER_API_KEY environment variable.
+impl Default for Credentials {
+    fn default() -> Self {
+        Credentials::Env("TRADIER_API_KEY".to_string())
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credentials::Static(_) => write!(f, "Static(..)"),
+            Credentials::Env(var) => write!(f, "Env({:?})", var),
+            Credentials::File(file) => write!(f, "File({:?})", file.path),
+            Credentials::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+impl TokenProvider for Credentials {
+    fn token(&self) -> BoxFuture<'_, Result<String, Error>> {
+        match self {
+            Credentials::Static(token) => Box::pin(async move { Ok(token.clone()) }),
+            Credentials::Env(var) => {
+                let token = env::var(var).map_err(|_| Error::MissingApiKey(var.clone()));
+                Box::pin(async move { token })
+            },
+            Credentials::File(file) => {
+                let token = file.token();
+                Box::pin(async move { token })
+            },
+            Credentials::Callback(f) => f(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenFile {
+    path: PathBuf,
+    cached: StdMutex<Option<(SystemTime, String)>>,
+}
+
+impl TokenFile {
+    fn token(&self) -> Result<String, Error> {
+        let err = |e:std::io::Error| Error::Auth(format!("Reading token file {}: {}", self.path.display(), e));
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).map_err(err)?;
+        let mut cached = self.cached.lock().unwrap();
+        match &*cached {
+            Some((at, token)) if *at == modified => Ok(token.clone()),
+            _ => {
+                let token = fs::read_to_string(&self.path).map_err(err)?.trim().to_string();
+                *cached = Some((modified, token.clone()));
+                Ok(token)
+            }
+        }
+    }
+}
+
+/// Response from the access token and refresh token endpoints.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AccessToken {
+    pub access_token: String,
+    /// Seconds until the access token expires.
+    pub expires_in: u64,
+    #[serde(default)]
+    pub issued_at: Option<String>,
+    #[serde(default)]
+    pub scope: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub status: String,
+}
+
+/// The client id and secret of a Tradier partner application.
+#[derive(Debug, Clone)]
+pub struct OAuthApp {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl OAuthApp {
+    pub fn new(client_id:&str, client_secret:&str) -> Self {
+        Self { client_id: client_id.to_string(), client_secret: client_secret.to_string() }
+    }
+
+    /// URL to send the user to for authorization. Tradier redirects back to the app's configured callback
+    /// with `code` and `state` query parameters.
+    pub fn authorization_url(&self, scopes:&[&str], state:&str) -> String {
+        Url::parse_with_params(AUTHORIZE_URL, &[("client_id", self.client_id.as_str()), ("scope", &scopes.join(",")), ("state", state)])
+            .unwrap()
+            .to_string()
+    }
+
+    /// Exchanges the code from the authorization callback for an access token.
+    pub async fn exchange_code(&self, code:&str) -> Result<AccessToken, Error> {
+        self.token_request(ACCESS_TOKEN_URL, &[("grant_type", "authorization_code"), ("code", code)]).await
+    }
+
+    pub async fn refresh(&self, refresh_token:&str) -> Result<AccessToken, Error> {
+        self.token_request(REFRESH_TOKEN_URL, &[("grant_type", "refresh_token"), ("refresh_token", refresh_token)]).await
+    }
+
+    async fn token_request(&self, url:&str, form:&[(&str, &str)]) -> Result<AccessToken, Error> {
+        let request = shared_client()
+            .post(url)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .header("Accept", "application/json")
+            .form(form);
+        parse_access_token(&send_request(request).await?.body)
+    }
+}
+
+fn parse_access_token(body:&str) -> Result<AccessToken, Error> {
+    let token = serde_json::from_str::<AccessToken>(body).map_err(|_| Error::Auth(format!("Unexpected token response: {}", body)))?;
+    if !token.status.is_empty() && token.status != "approved" {
+        return Err(Error::Auth(format!("Token request not approved: {}", token.status)));
+    }
+    Ok(token)
+}
+
+/// Token provider for OAuth access tokens that refreshes the token shortly before it expires,
+/// or after a request was rejected as unauthorized.
+pub struct OAuthTokenProvider {
+    app: OAuthApp,
+    current: Mutex<(AccessToken, Instant)>,
+    invalid: AtomicBool,
+}
+
+impl OAuthTokenProvider {
+    /// received is when the token was obtained, used with its expires_in to decide when to refresh.
+    pub fn new(app:OAuthApp, token:AccessToken, received:Instant) -> Self {
+        Self { app, current: Mutex::new((token, received)), invalid: AtomicBool::new(false) }
+    }
+
+    pub async fn access_token(&self) -> AccessToken {
+        self.current.lock().await.0.clone()
+    }
+}
+
+impl TokenProvider for OAuthTokenProvider {
+    fn token(&self) -> BoxFuture<'_, Result<String, Error>> {
+        Box::pin(async move {
+            let mut current = self.current.lock().await;
+            let (token, received) = &*current;
+            let expires = *received + Duration::from_secs(token.expires_in).saturating_sub(REFRESH_MARGIN);
+            if !self.invalid.load(Ordering::Acquire) && Instant::now() < expires {
+                return Ok(token.access_token.clone());
+            }
+            let refresh_token = token.refresh_token.clone()
+                .ok_or_else(|| Error::Auth("Access token expired and no refresh token is available".to_string()))?;
+            let mut refreshed = self.app.refresh(&refresh_token).await?;
+            if refreshed.refresh_token.is_none() {
+                refreshed.refresh_token = Some(refresh_token);
+            }
+            let access = refreshed.access_token.clone();
+            *current = (refreshed, Instant::now());
+            self.invalid.store(false, Ordering::Release);
+            Ok(access)
+        })
+    }
+
+    fn invalidate(&self) {
+        self.invalid.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(expires_in:u64, refresh_token:Option<&str>) -> AccessToken {
+        AccessToken {
+            access_token: "abc".to_string(), expires_in, issued_at: None, scope: "read".to_string(),
+            refresh_token: refresh_token.map(str::to_string), status: "approved".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_authorization_url() {
+        let app = OAuthApp::new("my id", "secret");
+        assert_eq!(app.authorization_url(&["read", "market"], "xyz"),
+            "https://api.tradier.com/v1/oauth/authorize?client_id=my+id&scope=read%2Cmarket&state=xyz");
+    }
+
+    #[test]
+    fn test_parse_access_token() {
+        let t = parse_access_token(r#"{"access_token":"abc","expires_in":86399,"issued_at":"2024-04-01T10:00:00.000+0000","scope":"read,write","refresh_token":"def","status":"approved"}"#).unwrap();
+        assert_eq!(t.access_token, "abc");
+        assert_eq!(t.expires_in, 86399);
+        assert_eq!(t.refresh_token.as_deref(), Some("def"));
+        assert!(matches!(parse_access_token(r#"{"access_token":"abc","expires_in":1,"status":"denied"}"#), Err(Error::Auth(_))));
+        assert!(matches!(parse_access_token("<html>"), Err(Error::Auth(_))));
+    }
+
+    #[tokio::test]
+    async fn test_provider() {
+        let app = OAuthApp::new("id", "secret");
+        let provider = OAuthTokenProvider::new(app.clone(), token(3600, None), Instant::now());
+        assert_eq!(provider.token().await.unwrap(), "abc");
+        // Without a refresh token an invalidated token can't be renewed.
+        provider.invalidate();
+        assert!(matches!(provider.token().await, Err(Error::Auth(_))));
+
+        let expired = OAuthTokenProvider::new(app, token(30, None), Instant::now());
+        assert!(matches!(expired.token().await, Err(Error::Auth(_))));
+    }
+
+    #[tokio::test]
+    async fn test_credentials() {
+        assert_eq!(Credentials::Static("abc".to_string()).token().await.unwrap(), "abc");
+        let missing = Credentials::Env("RUST_TRADIER_TEST_MISSING_VAR".to_string()).token().await.unwrap_err();
+        assert!(matches!(&missing, Error::MissingApiKey(var) if var == "RUST_TRADIER_TEST_MISSING_VAR"));
+        assert_eq!(missing.to_string(), "Required RUST_TRADIER_TEST_MISSING_VAR environment variable was not found");
+        let creds = Credentials::callback(|| Box::pin(async { Ok("from callback".to_string()) }));
+        assert_eq!(creds.token().await.unwrap(), "from callback");
+    }
+
+    #[tokio::test]
+    async fn test_credentials_file() {
+        let path = std::env::temp_dir().join(format!("rust-tradier-token-{}", std::process::id()));
+        fs::write(&path, "first\n").unwrap();
+        let creds = Credentials::file(&path);
+        assert_eq!(creds.token().await.unwrap(), "first");
+
+        fs::write(&path, "second").unwrap();
+        // Make sure the modified time changes even on file systems with coarse timestamps.
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        assert_eq!(creds.token().await.unwrap(), "second");
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(creds.token().await, Err(Error::Auth(_))));
+    }
+}
@@ -0,0 +1,139 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use crate::{events::TimesaleEvent, market_hours::{from_eastern, to_eastern}};
+
+/// An OHLCV bar. start is the UTC start of the bar's interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bar {
+    pub start: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: u64,
+}
+
+impl Bar {
+    fn merge(&mut self, high:f64, low:f64, close:f64, volume:u64) {
+        self.high = self.high.max(high);
+        self.low = self.low.min(low);
+        self.close = close;
+        self.volume += volume;
+    }
+}
+
+/// Start of the interval containing time. Intraday intervals align to the epoch, so eg. 5 minute bars start on
+/// 5 minute boundaries. Intervals of a day or more start at Eastern midnight so a daily bar covers one US session
+/// date, and multiples of a week start on Monday. time and the result are UTC.
+pub fn bar_start(time:DateTime<Utc>, interval:Duration) -> DateTime<Utc> {
+    let days = interval.num_days();
+    if days >= 1 {
+        let date = to_eastern(time.naive_utc()).date();
+        // 1970-01-05 was a Monday.
+        let anchor = NaiveDate::from_ymd_opt(1970, 1, if days % 7 == 0 { 5 } else { 1 }).unwrap();
+        let offset = (date - anchor).num_days().rem_euclid(days);
+        return from_eastern((date - Duration::days(offset)).and_hms_opt(0, 0, 0).unwrap()).and_utc();
+    }
+    let step = interval.num_milliseconds().max(1);
+    let millis = time.timestamp_millis();
+    let start = millis - millis.rem_euclid(step);
+    DateTime::from_timestamp_millis(start).unwrap_or_default()
+}
+
+/// Combines bars into bars of a longer interval, eg. 1 minute into 5 minute bars.
+/// Input must be sorted by start. Interval should be a multiple of the input interval, otherwise bars are
+/// assigned by their start time. Intervals with no input bars are skipped, not filled.
+pub fn resample(bars:&[Bar], interval:Duration) -> Vec<Bar> {
+    let mut out: Vec<Bar> = Vec::new();
+    for bar in bars {
+        let start = bar_start(bar.start, interval);
+        match out.last_mut() {
+            Some(last) if last.start == start => last.merge(bar.high, bar.low, bar.close, bar.volume),
+            _ => out.push(Bar { start, ..bar.clone() }),
+        }
+    }
+    out
+}
+
+/// Aggregates time and sales into bars. Input must be sorted by date.
+/// Canceled prints are skipped. Corrections are included as reported.
+pub fn bars_from_timesales(sales:&[TimesaleEvent], interval:Duration) -> Vec<Bar> {
+    let mut out: Vec<Bar> = Vec::new();
+    for sale in sales.iter().filter(|s| !s.cancel) {
+        let Some(time) = sale.time() else { continue };
+        let start = bar_start(time, interval);
+        match out.last_mut() {
+            Some(last) if last.start == start => last.merge(sale.last, sale.last, sale.last, sale.size),
+            _ => out.push(Bar { start, open: sale.last, high: sale.last, low: sale.last, close: sale.last, volume: sale.size }),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(min:i64, sec:i64) -> DateTime<Utc> {
+        chrono::NaiveDateTime::parse_from_str("2024-07-03 14:00:00", "%Y-%m-%d %H:%M:%S").unwrap().and_utc() + Duration::minutes(min) + Duration::seconds(sec)
+    }
+
+    fn bar(min:i64, open:f64, high:f64, low:f64, close:f64, volume:u64) -> Bar {
+        Bar { start: at(min, 0), open, high, low, close, volume }
+    }
+
+    #[test]
+    fn test_resample() {
+        let bars = [
+            bar(0, 10.0, 11.0, 9.5, 10.5, 100),
+            bar(1, 10.5, 12.0, 10.0, 11.0, 200),
+            bar(4, 11.0, 11.5, 9.0, 9.5, 50),
+            bar(5, 9.5, 10.0, 9.0, 9.8, 10),
+        ];
+        assert_eq!(resample(&bars, Duration::minutes(5)), [
+            bar(0, 10.0, 12.0, 9.0, 9.5, 350),
+            bar(5, 9.5, 10.0, 9.0, 9.8, 10),
+        ]);
+        assert_eq!(bar_start(at(7, 30), Duration::minutes(15)), at(0, 0));
+    }
+
+    #[test]
+    fn test_bar_start_days() {
+        let utc = |s:&str| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap().and_utc();
+        // 23:30 Eastern on the 3rd is already the 4th in UTC, but belongs to the 3rd's session.
+        assert_eq!(bar_start(utc("2024-07-04 03:30"), Duration::days(1)), utc("2024-07-03 04:00"));
+        assert_eq!(bar_start(utc("2024-07-03 14:00"), Duration::days(1)), utc("2024-07-03 04:00"));
+        // Winter time, Eastern midnight is 05:00 UTC.
+        assert_eq!(bar_start(utc("2024-01-08 20:59"), Duration::days(1)), utc("2024-01-08 05:00"));
+    }
+
+    #[test]
+    fn test_bar_start_weeks() {
+        use chrono::Datelike;
+        let utc = |s:&str| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap().and_utc();
+        // Wednesday to Monday.
+        assert_eq!(bar_start(utc("2024-07-03 14:00"), Duration::weeks(1)), utc("2024-07-01 04:00"));
+        // Sunday evening Eastern is Monday in UTC, but still the previous week.
+        assert_eq!(bar_start(utc("2024-07-08 01:00"), Duration::weeks(1)), utc("2024-07-01 04:00"));
+        assert_eq!(bar_start(utc("2024-07-08 04:00"), Duration::weeks(1)), utc("2024-07-08 04:00"));
+        assert_eq!(bar_start(utc("2024-07-08 04:00"), Duration::weeks(2)).date_naive().weekday(), chrono::Weekday::Mon);
+    }
+
+    #[test]
+    fn test_bars_from_timesales() {
+        let sale = |min:i64, sec:i64, last:f64, size:u64, cancel:bool| TimesaleEvent {
+            symbol: "SPY".to_string(), exchange: String::new(), bid: 0.0, ask: 0.0, last, size,
+            date: at(min, sec).timestamp_millis() as u64, seq: 0, flag: String::new(), cancel, correction: false, session: String::new(), extra: Default::default(),
+        };
+        let sales = [
+            sale(0, 1, 10.0, 100, false),
+            sale(0, 20, 10.4, 10, false),
+            sale(0, 30, 50.0, 10, true),
+            sale(0, 59, 9.9, 20, false),
+            sale(2, 0, 10.1, 5, false),
+        ];
+        assert_eq!(bars_from_timesales(&sales, Duration::minutes(1)), [
+            bar(0, 10.0, 10.4, 9.9, 9.9, 130),
+            bar(2, 10.1, 10.1, 10.1, 10.1, 5),
+        ]);
+    }
+}
@@ -0,0 +1,147 @@
+//! Blocking versions of the async functions for callers that don't run a tokio runtime.
+//! They run on a lazily created internal runtime, so they must not be called from within async code.
+
+use chrono::NaiveDate;
+use reqwest::Method;
+use std::{future::Future, sync::OnceLock};
+use tokio::runtime::{Builder, Runtime};
+use crate::{error::Error, http::{self, HttpTransport, RequestOptions, ResponseMeta}, markets::{self, HistoryInterval, HistoryOptions}};
+#[cfg(feature = "stream")]
+use crate::data::{self, Handler, StreamConfig};
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Builder::new_current_thread().enable_all().build().expect("Failed to create tokio runtime"))
+}
+
+fn block_on<F:Future>(future:F) -> F::Output {
+    runtime().block_on(future)
+}
+
+/// See [`http::tradier_get`].
+pub fn tradier_get(uri:&str) -> Result<String, Error> {
+    block_on(http::tradier_get(uri))
+}
+
+/// See [`http::tradier_post`].
+pub fn tradier_post(uri:&str) -> Result<String, Error> {
+    block_on(http::tradier_post(uri))
+}
+
+/// See [`http::tradier_request`].
+pub fn tradier_request(method:Method, uri:&str, opts:&RequestOptions) -> Result<String, Error> {
+    block_on(http::tradier_request(method, uri, opts))
+}
+
+/// See [`http::tradier_request_with`].
+pub fn tradier_request_with(transport:&dyn HttpTransport, method:Method, uri:&str, opts:&RequestOptions) -> Result<String, Error> {
+    block_on(http::tradier_request_with(transport, method, uri, opts))
+}
+
+/// See [`http::tradier_request_with_meta`].
+pub fn tradier_request_with_meta(method:Method, uri:&str, opts:&RequestOptions) -> Result<(String, ResponseMeta), Error> {
+    block_on(http::tradier_request_with_meta(method, uri, opts))
+}
+
+/// See [`markets::get_quotes`].
+pub fn get_quotes(symbols:&[&str], greeks:bool) -> Result<String, Error> {
+    block_on(markets::get_quotes(symbols, greeks))
+}
+
+/// See [`markets::get_quotes_with`].
+pub fn get_quotes_with(transport:&dyn HttpTransport, symbols:&[&str], greeks:bool) -> Result<String, Error> {
+    block_on(markets::get_quotes_with(transport, symbols, greeks))
+}
+
+/// See [`markets::get_quotes_batched`].
+pub fn get_quotes_batched(symbols:&[&str], greeks:bool, chunk_size:usize, parallelism:usize) -> Result<String, Error> {
+    block_on(markets::get_quotes_batched(symbols, greeks, chunk_size, parallelism))
+}
+
+/// See [`markets::get_quotes_batched_with`].
+pub fn get_quotes_batched_with(transport:&dyn HttpTransport, symbols:&[&str], greeks:bool, chunk_size:usize, parallelism:usize) -> Result<String, Error> {
+    block_on(markets::get_quotes_batched_with(transport, symbols, greeks, chunk_size, parallelism))
+}
+
+/// See [`markets::get_history`].
+pub fn get_history(symbol:&str, interval:HistoryInterval, start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<String, Error> {
+    markets::check_range(start, end)?;
+    block_on(markets::get_history(symbol, interval, start, end, options))
+}
+
+/// See [`markets::get_history_with`].
+pub fn get_history_with(transport:&dyn HttpTransport, symbol:&str, interval:HistoryInterval, start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<String, Error> {
+    markets::check_range(start, end)?;
+    block_on(markets::get_history_with(transport, symbol, interval, start, end, options))
+}
+
+/// Streams the symbols to handler on the current thread until the handler is done. See [`data::run_async`].
+#[cfg(feature = "stream")]
+pub fn run<H:Handler<String> + 'static + Send + Sync>(handler:H, symbols:&[&str]) {
+    block_on(data::run_async(handler, symbols))
+}
+
+/// See [`data::run_async_with`].
+#[cfg(feature = "stream")]
+pub fn run_with<H:Handler<String> + 'static + Send + Sync>(handler:H, symbols:&[&str], config:&StreamConfig) {
+    block_on(data::run_async_with(handler, symbols, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::MockTransport;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_blocking_request() {
+        let transport = MockTransport::new()
+            .with_response("/blocking-test", StatusCode::OK, "ok")
+            .with_response("/markets/quotes", StatusCode::OK, "quotes")
+            .with_response("/markets/history", StatusCode::OK, "history");
+        assert_eq!(tradier_request_with(&transport, Method::GET, "/blocking-test", &RequestOptions::default()).unwrap(), "ok");
+        assert_eq!(get_quotes_with(&transport, &["SPY"], false).unwrap(), "quotes");
+        let day = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        assert_eq!(get_history_with(&transport, "SPY", HistoryInterval::Daily, day, day, &HistoryOptions::default()).unwrap(), "history");
+        assert!(matches!(get_history_with(&transport, "SPY", HistoryInterval::Daily, day, day.pred_opt().unwrap(), &HistoryOptions::default()), Err(Error::InvalidRequest(_))));
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_blocking_across_runtimes() {
+        use crate::{auth::Credentials, http::ReqwestTransport};
+        use std::{io::{Read, Write}, sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::Duration};
+
+        // Keep-alive server answering every request with "ok", counting connections.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let count = connections.clone();
+        std::thread::spawn(move || for stream in listener.incoming() {
+            count.fetch_add(1, Ordering::SeqCst);
+            let mut stream = stream.unwrap();
+            std::thread::spawn(move || {
+                let mut buf = [0; 4096];
+                while matches!(stream.read(&mut buf), Ok(n) if n > 0) {
+                    let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+                }
+            });
+        });
+
+        let transport = ReqwestTransport::with_timeouts(Arc::new(Credentials::Static("key".to_string())), Duration::from_secs(2), Duration::from_secs(2))
+            .base_url(&url);
+        let opts = RequestOptions::default();
+        let get = || http::tradier_request_with(&transport, Method::GET, "/test", &opts);
+        let other_runtime = || Builder::new_current_thread().enable_all().build().unwrap();
+
+        // The connection opened here would be stuck on this idle runtime if it drove it.
+        let idle = other_runtime();
+        assert_eq!(idle.block_on(get()).unwrap(), "ok");
+        assert_eq!(tradier_request_with(&transport, Method::GET, "/test", &opts).unwrap(), "ok");
+        assert_eq!(tradier_request_with(&transport, Method::GET, "/test", &opts).unwrap(), "ok");
+        drop(idle);
+        assert_eq!(other_runtime().block_on(get()).unwrap(), "ok");
+        assert_eq!(tradier_request_with(&transport, Method::GET, "/test", &opts).unwrap(), "ok");
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+}
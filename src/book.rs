@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use crate::{data::Handler, events::{QuoteEvent, StreamEvent}, throttle::warn_throttled};
+
+/// National best bid and offer for a symbol. Exchange times come from the quote, received is when it arrived.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nbbo {
+    pub bid: f64,
+    pub bid_size: u64,
+    pub bid_exchange: String,
+    pub bid_time: Option<DateTime<Utc>>,
+    pub ask: f64,
+    pub ask_size: u64,
+    pub ask_exchange: String,
+    pub ask_time: Option<DateTime<Utc>>,
+    pub received: DateTime<Utc>,
+}
+
+impl Nbbo {
+    fn from_quote(received:DateTime<Utc>, q:&QuoteEvent) -> Self {
+        Self {
+            bid: q.bid, bid_size: q.bid_size, bid_exchange: q.bid_exchange.clone(), bid_time: q.bid_time(),
+            ask: q.ask, ask_size: q.ask_size, ask_exchange: q.ask_exchange.clone(), ask_time: q.ask_time(),
+            received,
+        }
+    }
+
+    /// The later of the bid and ask exchange times.
+    pub fn quote_time(&self) -> Option<DateTime<Utc>> {
+        self.bid_time.max(self.ask_time)
+    }
+
+    pub fn spread(&self) -> f64 {
+        self.ask - self.bid
+    }
+
+    pub fn mid(&self) -> f64 {
+        (self.bid + self.ask) / 2.0
+    }
+
+    /// Locked when bid equals ask, crossed when bid is above ask. Sides with no price (0) are ignored.
+    pub fn condition(&self) -> Option<MarketCondition> {
+        if self.bid <= 0.0 || self.ask <= 0.0 {
+            None
+        } else if self.bid > self.ask {
+            Some(MarketCondition::Crossed)
+        } else if self.bid == self.ask {
+            Some(MarketCondition::Locked)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketCondition {
+    Locked,
+    Crossed,
+}
+
+/// Consumes typed stream events and keeps the current NBBO per symbol.
+/// Locked and crossed markets are reported as (throttled) warnings when they appear.
+#[derive(Debug, Default)]
+pub struct BookBuilder {
+    books: HashMap<String, Nbbo>,
+}
+
+impl BookBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn best_bid_ask(&self, symbol:&str) -> Option<&Nbbo> {
+        self.books.get(symbol)
+    }
+
+    /// Applies a quote and returns the market condition if it just became locked or crossed.
+    pub fn update(&mut self, received:DateTime<Utc>, quote:&QuoteEvent) -> Option<MarketCondition> {
+        let nbbo = Nbbo::from_quote(received, quote);
+        let condition = nbbo.condition();
+        let previous = self.books.insert(quote.symbol.clone(), nbbo).and_then(|prev| prev.condition());
+        condition.filter(|c| previous != Some(*c))
+    }
+}
+
+impl Handler<StreamEvent> for BookBuilder {
+    fn on_data(&mut self, timestamp:DateTime<Utc>, data:StreamEvent) {
+        if let StreamEvent::Quote(q) = data {
+            if let Some(condition) = self.update(timestamp, &q) {
+                warn_throttled(&format!("book:{}", q.symbol), &format!("{}: {} market for {}: bid {} ({}) ask {} ({})",
+                    timestamp, if condition == MarketCondition::Crossed { "Crossed" } else { "Locked" },
+                    q.symbol, q.bid, q.bid_exchange, q.ask, q.ask_exchange));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(bid:f64, ask:f64) -> QuoteEvent {
+        QuoteEvent {
+            symbol: "SPY".to_string(), bid, bid_size: 10, bid_exchange: "Q".to_string(), bid_date: 1557757189000,
+            ask, ask_size: 5, ask_exchange: "Z".to_string(), ask_date: 0, extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_book_builder() {
+        let now = DateTime::<Utc>::default();
+        let mut book = BookBuilder::new();
+        assert_eq!(book.update(now, &quote(500.0, 500.1)), None);
+        let nbbo = book.best_bid_ask("SPY").unwrap();
+        assert!((nbbo.spread() - 0.1).abs() < 1e-9);
+        assert_eq!(nbbo.bid_time, DateTime::from_timestamp_millis(1557757189000));
+        assert_eq!(nbbo.ask_time, None);
+        assert_eq!(nbbo.quote_time(), nbbo.bid_time);
+        assert_eq!(nbbo.received, DateTime::<Utc>::UNIX_EPOCH);
+
+        assert_eq!(book.update(now, &quote(500.1, 500.1)), Some(MarketCondition::Locked));
+        assert_eq!(book.update(now, &quote(500.1, 500.1)), None);
+        assert_eq!(book.update(now, &quote(500.2, 500.1)), Some(MarketCondition::Crossed));
+        assert_eq!(book.update(now, &quote(0.0, 500.1)), None);
+        assert!(book.best_bid_ask("QQQ").is_none());
+    }
+}
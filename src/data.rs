@@ -1,12 +1,149 @@
-use chrono::{NaiveDateTime, Utc};
-use std::{env, time::Duration};
+use chrono::{DateTime, Utc};
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::sync::broadcast;
+use crate::{http::HttpTransport, net::NetworkConfig, watchdog::FeedHealth};
+#[cfg(feature = "stream")]
+use std::{collections::BTreeSet, time::Instant};
+#[cfg(feature = "stream")]
 use futures_util::{StreamExt, SinkExt};
 use serde_json::{Value,json};
-use tokio::{runtime::Builder, time::timeout};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, WebSocketStream};
+#[cfg(feature = "stream")]
+use tokio::time::timeout;
+#[cfg(feature = "stream")]
+use tokio_tungstenite::{tungstenite::protocol::Message, WebSocketStream};
+#[cfg(feature = "stream")]
+use reqwest::Method;
+#[cfg(feature = "stream")]
+use crate::{error::Error, http::{tradier_post, tradier_request_with, RequestOptions}, markets::{get_quotes, get_quotes_with}, throttle::warn_throttled, watchdog::{payload_symbol, FeedWatchdog}};
+
+/// See: https://documentation.tradier.com/brokerage-api/streaming/get-markets-events
+pub const STREAM_URL: &str = "wss://ws.tradier.com/v1/markets/events";
+
+/// Delay before the first reconnect.
+#[cfg(feature = "stream")]
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Max delay between attempts when creating a session or connecting keeps failing.
+#[cfg(feature = "stream")]
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// A connection that lasted this long resets the reconnect backoff.
+#[cfg(feature = "stream")]
+pub const HEALTHY_CONNECTION: Duration = Duration::from_secs(60);
+
+/// What the run loop should do after a connection ends, with the reason it ended.
+#[cfg(feature = "stream")]
+enum RunResult {
+    Exit(String),
+    /// The connection was established then ended, reconnect with a new session after the backoff delay.
+    Reconnect(String),
+    /// Connecting failed, reconnect after the backoff delay.
+    Retry(String),
+}
+
+/// Connection state changes, see [`StreamConfig::connection_events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    Connecting,
+    Connected,
+    Subscribed { symbols: Vec<String> },
+    Disconnected { reason: String },
+    /// attempt counts from 1 since the last connection that lasted [`HEALTHY_CONNECTION`].
+    Reconnecting { attempt: u32 },
+}
 
 pub trait Handler<T> {
-    fn on_data(&mut self, timestamp:NaiveDateTime, data:T);
+    fn on_data(&mut self, timestamp:DateTime<Utc>, data:T);
+
+    /// Checked after each message. Returning true closes the connection and ends the run loop.
+    fn is_done(&self) -> bool {
+        false
+    }
+
+    /// Called when the watchdog detects stale data or recovery, see [`StreamConfig::stale_after`].
+    fn on_feed_health(&mut self, health:FeedHealth) {
+        println!("{}: Feed health: {:?}", Utc::now(), health);
+    }
+}
+
+#[derive(Clone)]
+pub struct StreamConfig {
+    /// Report symbols, and the connection, as stale when no message arrives for them within this window.
+    /// A stale connection is reconnected. None disables the watchdog.
+    pub stale_after: Option<Duration>,
+    /// Only watch for stale data during regular market hours.
+    pub market_hours_only: bool,
+    /// Websocket to connect to, [`STREAM_URL`] by default.
+    pub url: String,
+    /// After each subscription, fetch a REST quote for every symbol and deliver it to the handler before any
+    /// streamed message, so illiquid symbols don't wait for their first update. Snapshots are delivered in
+    /// the stream's quote format with `"snapshot":true` added, see [`crate::events::QuoteEvent::is_snapshot`].
+    pub snapshot_on_subscribe: bool,
+    /// Send a ping after this long without receiving anything. Defaults to 100 seconds.
+    pub ping_interval: Duration,
+    /// How long to wait for a pong, or any other message, after a ping. Defaults to 10 seconds.
+    pub pong_timeout: Duration,
+    /// Reconnect after this many pings in a row go unanswered. A ping is resent after each miss. Defaults to 2.
+    pub max_missed_pongs: u32,
+    /// Proxy and extra root certificates for the websocket. Session creation goes through the REST transport,
+    /// configure that with [`crate::http::ReqwestTransport::with_network`].
+    pub network: NetworkConfig,
+    events: Option<broadcast::Sender<ConnectionEvent>>,
+    transport: Option<Arc<dyn HttpTransport>>,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig {
+            stale_after: None, market_hours_only: true, url: STREAM_URL.to_string(), snapshot_on_subscribe: false,
+            ping_interval: Duration::from_secs(100), pong_timeout: Duration::from_secs(10), max_missed_pongs: 2,
+            network: NetworkConfig::default(), events: None, transport: None,
+        }
+    }
+}
+
+impl fmt::Debug for StreamConfig {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamConfig")
+            .field("stale_after", &self.stale_after)
+            .field("market_hours_only", &self.market_hours_only)
+            .field("url", &self.url)
+            .field("snapshot_on_subscribe", &self.snapshot_on_subscribe)
+            .field("ping_interval", &self.ping_interval)
+            .field("pong_timeout", &self.pong_timeout)
+            .field("max_missed_pongs", &self.max_missed_pongs)
+            .field("network", &self.network)
+            .field("events", &self.events.is_some())
+            .field("transport", &self.transport.is_some())
+            .finish()
+    }
+}
+
+impl StreamConfig {
+    /// Creates streaming sessions through this transport instead of the global one set with [`crate::http::set_transport`].
+    pub fn transport(mut self, transport:Arc<dyn HttpTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Subscribes to connection state changes for streams run with this config.
+    /// Receivers that fall more than 64 events behind miss the oldest ones.
+    pub fn connection_events(&mut self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.get_or_insert_with(|| broadcast::channel(64).0).subscribe()
+    }
+
+    #[cfg(feature = "stream")]
+    fn emit(&self, event:ConnectionEvent) {
+        if let Some(tx) = &self.events {
+            // Only fails when there are no receivers.
+            let _ = tx.send(event);
+        }
+    }
+}
+
+/// A message along with the time it was received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketData<T> {
+    pub timestamp: DateTime<Utc>,
+    pub data: T,
 }
 
 // pub fn start<H:Handler<String> + 'static + Send + Sync>(mut handler:H, symbols:&str) {
@@ -31,144 +168,464 @@ pub trait Handler<T> {
 // }
 
 /// symbols is comma separated string of symbols to subscribe
-pub async fn run_async<H:Handler<String> + 'static + Send + Sync>(mut handler:H, symbols:&[&str]) {
+#[cfg(feature = "stream")]
+pub async fn run_async<H:Handler<String> + 'static + Send + Sync>(handler:H, symbols:&[&str]) {
+    run_async_with(handler, symbols, &StreamConfig::default()).await
+}
+
+/// Streams until the handler is done, reconnecting with a new session whenever the connection ends, including
+/// when the server closes it, since Tradier closes idle or expired sessions. Reconnects and failed connection
+/// attempts back off from 1 second, doubling up to [`MAX_RETRY_DELAY`], so a persistently failing session
+/// endpoint isn't hammered. The delay resets once a connection has stayed up for [`HEALTHY_CONNECTION`].
+#[cfg(feature = "stream")]
+pub async fn run_async_with<H:Handler<String> + 'static + Send + Sync>(mut handler:H, symbols:&[&str], config:&StreamConfig) {
     println!("Setting up listening on websocket client");
     // let rt = Builder::new_current_thread().enable_io().enable_time().build().unwrap(); // new_multi_thread().worker_threads(4).enable_all().build().unwrap();
     // tokio::runtime::Runtime::new().unwrap();
     // rt.block_on(async move {
-    while run(&mut handler, symbols).await {}
+    let mut delay = INITIAL_RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        config.emit(ConnectionEvent::Connecting);
+        let started = Instant::now();
+        let result = run(&mut handler, symbols, config).await;
+        let (RunResult::Exit(reason) | RunResult::Reconnect(reason) | RunResult::Retry(reason)) = &result;
+        config.emit(ConnectionEvent::Disconnected { reason: reason.clone() });
+        #[cfg(feature = "metrics")]
+        if !matches!(result, RunResult::Exit(_)) {
+            crate::metrics::record_reconnect();
+        }
+        match result {
+            RunResult::Exit(_) => break,
+            RunResult::Reconnect(_) if started.elapsed() >= HEALTHY_CONNECTION => {
+                delay = INITIAL_RETRY_DELAY;
+                attempt = 0;
+            },
+            RunResult::Reconnect(_) | RunResult::Retry(_) => (),
+        }
+        attempt += 1;
+        config.emit(ConnectionEvent::Reconnecting { attempt });
+        println!("Reconnecting websocket in {:?}", delay);
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
+    }
     // });
 }
 
-/// Returns true if the caller should attempt to reconnect, or false if the caller should exit.
-async fn run<H:Handler<String> + 'static + Send + Sync>(handler:&mut H, symbols:&[&str]) -> bool {
+/// Streams a large symbol set over several websocket sessions of at most max_per_session symbols each,
+/// with a clone of the handler per session. Returns once every session's handler is done.
+/// The symbol set is fixed for the run, use [`ShardedStream`] to add and remove symbols while streaming.
+#[cfg(feature = "stream")]
+pub async fn run_sharded_async<H:Handler<String> + Clone + 'static + Send + Sync>(handler:H, symbols:&[&str], max_per_session:usize, config:&StreamConfig) {
+    let shards = shard_symbols(symbols, max_per_session);
+    futures_util::future::join_all(shards.iter().map(|shard| run_async_with(handler.clone(), shard, config))).await;
+}
+
+/// Splits symbols into the fewest sessions of at most max_per_session symbols, sizes as even as possible.
+/// Symbols are sorted and deduplicated first, so the same set always gives the same assignment.
+pub fn shard_symbols<'a>(symbols:&[&'a str], max_per_session:usize) -> Vec<Vec<&'a str>> {
+    let mut symbols = symbols.to_vec();
+    symbols.sort_unstable();
+    symbols.dedup();
+    if symbols.is_empty() {
+        return Vec::new();
+    }
+    let count = symbols.len().div_ceil(max_per_session.max(1));
+    let (base, extra) = (symbols.len() / count, symbols.len() % count);
+    let mut rest = symbols.as_slice();
+    (0..count).map(|i| {
+        let (shard, tail) = rest.split_at(base + usize::from(i < extra));
+        rest = tail;
+        shard.to_vec()
+    }).collect()
+}
+
+/// Streams a changing symbol set over as many sessions as needed, each with a clone of the handler.
+/// Existing symbols keep their session. Added symbols fill one session with room before starting new ones, and
+/// removed symbols leave a gap rather than moving others, so only sessions whose symbols changed are restarted.
+/// Sessions run as tokio tasks, so this must be created and changed within a runtime. Dropping it stops them.
+#[cfg(feature = "stream")]
+pub struct ShardedStream<H> {
+    handler: H,
+    config: StreamConfig,
+    max_per_session: usize,
+    symbols: BTreeSet<String>,
+    shards: Vec<Shard>,
+}
+
+#[cfg(feature = "stream")]
+struct Shard {
+    symbols: Vec<String>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "stream")]
+impl<H:Handler<String> + Clone + 'static + Send + Sync> ShardedStream<H> {
+    pub fn new(handler:H, symbols:&[&str], max_per_session:usize, config:StreamConfig) -> Self {
+        let mut stream = Self { handler, config, max_per_session: max_per_session.max(1), symbols: BTreeSet::new(), shards: Vec::new() };
+        stream.subscribe(symbols);
+        stream
+    }
+
+    /// Adds the symbols not already subscribed, restarting at most one running session.
+    pub fn subscribe(&mut self, symbols:&[&str]) {
+        let mut added: Vec<String> = symbols.iter().filter(|s| !self.symbols.contains(**s)).map(|s| s.to_string()).collect();
+        added.sort_unstable();
+        added.dedup();
+        if added.is_empty() {
+            return;
+        }
+        self.symbols.extend(added.iter().cloned());
+        let mut added = added.into_iter().peekable();
+        if let Some(i) = self.shards.iter().position(|shard| shard.symbols.len() < self.max_per_session) {
+            let room = self.max_per_session - self.shards[i].symbols.len();
+            let mut symbols = self.shards[i].symbols.clone();
+            symbols.extend(added.by_ref().take(room));
+            self.restart(i, symbols);
+        }
+        while added.peek().is_some() {
+            let symbols: Vec<String> = added.by_ref().take(self.max_per_session).collect();
+            let task = self.spawn(&symbols);
+            self.shards.push(Shard { symbols, task });
+        }
+    }
+
+    /// Removes the symbols, restarting the sessions that had them and stopping any left empty.
+    pub fn unsubscribe(&mut self, symbols:&[&str]) {
+        for symbol in symbols {
+            self.symbols.remove(*symbol);
+        }
+        let mut i = 0;
+        while i < self.shards.len() {
+            let kept: Vec<String> = self.shards[i].symbols.iter().filter(|s| self.symbols.contains(*s)).cloned().collect();
+            if kept.is_empty() {
+                self.shards.remove(i).task.abort();
+                continue;
+            }
+            if kept.len() < self.shards[i].symbols.len() {
+                self.restart(i, kept);
+            }
+            i += 1;
+        }
+    }
+
+    /// All subscribed symbols, sorted.
+    pub fn symbols(&self) -> Vec<&str> {
+        self.symbols.iter().map(String::as_str).collect()
+    }
+
+    /// The symbols of each running session.
+    pub fn shards(&self) -> Vec<Vec<&str>> {
+        self.shards.iter().map(|shard| shard.symbols.iter().map(String::as_str).collect()).collect()
+    }
+
+    fn restart(&mut self, i:usize, symbols:Vec<String>) {
+        self.shards[i].task.abort();
+        let task = self.spawn(&symbols);
+        self.shards[i] = Shard { symbols, task };
+    }
+
+    fn spawn(&self, symbols:&[String]) -> tokio::task::JoinHandle<()> {
+        let (handler, config, subscribe) = (self.handler.clone(), self.config.clone(), symbols.to_vec());
+        tokio::spawn(async move {
+            let subscribe: Vec<&str> = subscribe.iter().map(String::as_str).collect();
+            run_async_with(handler, &subscribe, &config).await
+        })
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<H> Drop for ShardedStream<H> {
+    fn drop(&mut self) {
+        for shard in &self.shards {
+            shard.task.abort();
+        }
+    }
+}
+
+/// Each call creates a new streaming session, since sessions expire and can't be reused after a disconnect.
+#[cfg(feature = "stream")]
+async fn run<H:Handler<String> + 'static + Send + Sync>(handler:&mut H, symbols:&[&str], config:&StreamConfig) -> RunResult {
     println!("In websocket thread");
-    // TODO: if stream breaks, try to fix it
-    let (sid, ws_stream) = connect().await;
+    let (sid, ws_stream) = match connect(config).await {
+        Ok(connected) => connected,
+        Err(e) => {
+            println!("{}: Error connecting to websocket: {}", Utc::now(), e);
+            return RunResult::Retry(e.to_string());
+        }
+    };
+    config.emit(ConnectionEvent::Connected);
     let (mut write, mut read) = ws_stream.split();
     // let symbols_str = symbols.join(",");
-    let payload = json!({ "symbols": symbols, "sessionid": sid, "linebreak": false }).to_string();
+    let payload = json!({ "symbols": symbols, "sessionid": sid, "linebreak": true }).to_string();
     println!("Payload sending: {}", payload);
     match write.send(Message::Text(payload)).await {
-        Ok(o) => println!("Successful subscription: {:?}", o),
+        Ok(o) => {
+            println!("Successful subscription: {:?}", o);
+            config.emit(ConnectionEvent::Subscribed { symbols: symbols.iter().map(|s| s.to_string()).collect() });
+        },
         Err(err) => {
             println!("Error when submitting subscription: {:?}", err);
-            return false;
+            return RunResult::Retry(format!("Error submitting subscription: {}", err));
         },
     }
+    if config.snapshot_on_subscribe {
+        let now = Utc::now();
+        for payload in fetch_snapshots(symbols, config).await {
+            handler.on_data(now, payload);
+            if handler.is_done() {
+                println!("{}: Exiting: Handler is done", now);
+                let _ = write.close().await;
+                return RunResult::Exit("Handler is done".to_string());
+            }
+        }
+    }
+    let mut watchdog = config.stale_after.map(|stale_after| FeedWatchdog::new(symbols, stale_after, config.market_hours_only, Utc::now()));
+    let check_every = config.stale_after.map(|d| (d / 4).max(Duration::from_secs(1)));
+    let mut next_check = check_every.map(|d| Instant::now() + d);
+    let mut keepalive = Keepalive::new(config, Instant::now());
     loop {
-        match timeout(Duration::from_secs(100), read.next()).await {
-            Err(elapsed) => {
-                println!("{}: Websocket read timed out |{}|. Sending ping.", Utc::now().naive_utc(), elapsed);
-                match write.send(Message::Ping(Vec::new())).await {
-                    Ok(_) => continue,
-                    Err(e) => {
-                        println!("Exiting: Error sending ping after timeout. {}", e);
-                        return false;
+        let mut deadline = keepalive.deadline();
+        if let Some(at) = next_check {
+            deadline = deadline.min(at);
+        }
+        match timeout(deadline.saturating_duration_since(Instant::now()), read.next()).await {
+            Err(_) => match keepalive.check(Instant::now()) {
+                KeepaliveAction::Wait => (),
+                KeepaliveAction::Ping => {
+                    println!("{}: Websocket read timed out. Sending ping.", Utc::now());
+                    if let Err(e) = write.send(Message::Ping(Vec::new())).await {
+                        println!("Reconnecting: Error sending ping after timeout. {}", e);
+                        return RunResult::Reconnect(format!("Error sending ping: {}", e));
                     }
-                }
+                },
+                KeepaliveAction::Disconnect => {
+                    println!("{}: Reconnecting: {} pings went unanswered", Utc::now(), config.max_missed_pongs);
+                    let _ = write.close().await;
+                    return RunResult::Reconnect(format!("{} pings went unanswered", config.max_missed_pongs));
+                },
             },
 
             Ok(None) => {
-                println!("Exiting: Websocket read.next returned None.");
-                return false;
+                println!("Reconnecting: Websocket read.next returned None.");
+                return RunResult::Reconnect("Websocket stream ended".to_string());
             },
 
             Ok(Some(msg)) => {
                 // if let Some(msg) = timeout(Duration::from_secs(100), read.next()).await {
-                let now = Utc::now().naive_utc();
+                let now = Utc::now();
+                keepalive.received(Instant::now());
                 // println!("Received message: {:?}", msg);
                 match msg {
-                    Ok(Message::Text(payload)) => {
+                    Ok(Message::Text(text)) => {
                         // println!("Received text: {:?}", text);
-                        handler.on_data(now, payload);
+                        for payload in split_messages(&text) {
+                            if is_session_error(payload) {
+                                println!("{}: Reconnecting: Session error: {}", now, payload);
+                                let _ = write.close().await;
+                                return RunResult::Reconnect(format!("Session error: {}", payload));
+                            }
+                            let symbol = payload_symbol(payload);
+                            if let Some(recovered) = watchdog.as_mut().and_then(|w| w.record(now, symbol)) {
+                                handler.on_feed_health(recovered);
+                            }
+                            #[cfg(feature = "metrics")]
+                            let started = Instant::now();
+                            handler.on_data(now, payload.to_string());
+                            #[cfg(feature = "metrics")]
+                            crate::metrics::record_message(symbol, started.elapsed());
+                            if handler.is_done() {
+                                println!("{}: Exiting: Handler is done", now);
+                                let _ = write.close().await;
+                                return RunResult::Exit("Handler is done".to_string());
+                            }
+                        }
                     }
                     Ok(Message::Binary(payload)) => {
-                        println!("{}: Received binary: {:?}", now, payload);
+                        warn_throttled("stream:binary", &format!("{}: Received binary: {:?}", now, payload));
                     }
                     Ok(Message::Ping(payload)) => {
-                        println!("{}: Received ping: {:?}", now, payload);
+                        warn_throttled("stream:ping", &format!("{}: Received ping: {:?}", now, payload));
                     }
                     Ok(Message::Pong(payload)) => {
-                        println!("{}: Received pong: {:?}", now, payload);
+                        warn_throttled("stream:pong", &format!("{}: Received pong: {:?}", now, payload));
                     }
                     Ok(Message::Close(payload)) => {
-                        println!("{}: Exiting: Received close: {:?}", now, payload);
-                        return false;
+                        println!("{}: Reconnecting: Received close: {:?}", now, payload);
+                        return RunResult::Reconnect(format!("Received close: {:?}", payload));
                     }
                     Err(e) => {
                         println!("Error at {:?}: {:?}", now, e);
-                        break;
+                        return RunResult::Reconnect(format!("Read error: {}", e));
                     },
                     _ => {
                         println!("Other at {:?}: {:?}", now, msg);
-                        break;
+                        return RunResult::Reconnect(format!("Unexpected message: {:?}", msg));
                     }
                 }
             }
         }
+
+        if let (Some(w), Some(at), Some(every)) = (watchdog.as_mut(), next_check, check_every) {
+            if Instant::now() >= at {
+                next_check = Some(Instant::now() + every);
+                let mut reconnect = false;
+                for health in w.check(Utc::now()) {
+                    reconnect |= matches!(health, FeedHealth::ConnectionStale { .. });
+                    handler.on_feed_health(health);
+                }
+                if reconnect {
+                    println!("{}: No data received within {:?}, reconnecting", Utc::now(), config.stale_after.unwrap_or_default());
+                    let _ = write.close().await;
+                    return RunResult::Reconnect("No data received within the stale window".to_string());
+                }
+            }
+        }
     }
-    true
 }
 
-async fn connect() -> (String, WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>) {
-    let resp = tradier_post("/markets/events/session").await.unwrap();
+#[cfg(feature = "stream")]
+async fn connect(config:&StreamConfig) -> Result<(String, WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>), Error> {
+    let resp = match &config.transport {
+        Some(transport) => tradier_request_with(&**transport, Method::POST, "/markets/events/session", &RequestOptions::default()).await?,
+        None => tradier_post("/markets/events/session").await?,
+    };
     println!("{}", resp);
-    let data = serde_json::from_str::<Value>(&resp).unwrap();
-    let s = &data["stream"];
-    let sid = s["sessionid"].as_str().unwrap().to_string();
+    let sid = parse_session_id(&resp).ok_or_else(|| Error::Stream(format!("No session id in response: {}", resp)))?;
     // let url = s["url"].as_str().unwrap();
-    // See: https://documentation.tradier.com/brokerage-api/streaming/get-markets-events
-    let url = "wss://ws.tradier.com/v1/markets/events";
-    let url_parsed = reqwest::Url::parse(url).unwrap();
+    let url = &config.url;
+    let url_parsed = reqwest::Url::parse(url).map_err(|e| Error::Stream(format!("Invalid stream url {}: {}", url, e)))?;
     println!("Connecting to websocket {} with session id {}", url, sid);
 
-    let (ws_stream, _) = connect_async(url_parsed).await.expect("Failed to connect");
+    let ws_stream = config.network.connect_websocket(url_parsed).await?;
     println!("WebSocket handshake has been successfully completed");
-    (sid, ws_stream)
+    Ok((sid, ws_stream))
+}
+
+#[cfg(feature = "stream")]
+#[derive(Debug, PartialEq, Eq)]
+enum KeepaliveAction {
+    Wait,
+    Ping,
+    Disconnect,
+}
+
+/// Decides when to ping and when to give up on a connection that stopped answering.
+/// Any message received counts as an answer, not only pongs.
+#[cfg(feature = "stream")]
+#[derive(Debug)]
+struct Keepalive {
+    ping_interval: Duration,
+    pong_timeout: Duration,
+    max_missed: u32,
+    last_read: Instant,
+    ping_sent: Option<Instant>,
+    missed: u32,
+}
+
+#[cfg(feature = "stream")]
+impl Keepalive {
+    fn new(config:&StreamConfig, now:Instant) -> Self {
+        Self {
+            ping_interval: config.ping_interval, pong_timeout: config.pong_timeout, max_missed: config.max_missed_pongs.max(1),
+            last_read: now, ping_sent: None, missed: 0,
+        }
+    }
+
+    fn received(&mut self, now:Instant) {
+        self.last_read = now;
+        self.ping_sent = None;
+        self.missed = 0;
+    }
+
+    /// When [`Keepalive::check`] next has something to do.
+    fn deadline(&self) -> Instant {
+        match self.ping_sent {
+            Some(sent) => sent + self.pong_timeout,
+            None => self.last_read + self.ping_interval,
+        }
+    }
+
+    fn check(&mut self, now:Instant) -> KeepaliveAction {
+        if now < self.deadline() {
+            return KeepaliveAction::Wait;
+        }
+        if self.ping_sent.is_some() {
+            self.missed += 1;
+            if self.missed >= self.max_missed {
+                return KeepaliveAction::Disconnect;
+            }
+        }
+        self.ping_sent = Some(now);
+        KeepaliveAction::Ping
+    }
+}
+
+/// Failures are warned about and give no snapshots, the stream carries on regardless.
+#[cfg(feature = "stream")]
+async fn fetch_snapshots(symbols:&[&str], config:&StreamConfig) -> Vec<String> {
+    let result = match &config.transport {
+        Some(transport) => get_quotes_with(&**transport, symbols, false).await,
+        None => get_quotes(symbols, false).await,
+    };
+    match result {
+        Ok(body) => snapshot_messages(&body),
+        Err(e) => {
+            warn_throttled("stream:snapshot", &format!("Error fetching quote snapshots: {}", e));
+            Vec::new()
+        },
+    }
 }
 
+/// Converts a `/markets/quotes` response into stream quote messages marked as snapshots.
+/// quote is an object for a single symbol and a list for several.
+pub(crate) fn snapshot_messages(body:&str) -> Vec<String> {
+    let Ok(data) = serde_json::from_str::<Value>(body) else { return Vec::new() };
+    let quotes = match &data["quotes"]["quote"] {
+        Value::Array(list) => list.iter().collect(),
+        Value::Object(_) => vec![&data["quotes"]["quote"]],
+        _ => Vec::new(),
+    };
+    quotes.into_iter().filter(|q| q["symbol"].is_string()).map(|q| json!({
+        "type": "quote", "symbol": q["symbol"], "snapshot": true,
+        "bid": q["bid"].as_f64().unwrap_or_default(), "bidsz": q["bidsize"].as_u64().unwrap_or_default(),
+        "bidexch": q["bidexch"].as_str().unwrap_or_default(), "biddate": q["bid_date"].as_u64().unwrap_or_default(),
+        "ask": q["ask"].as_f64().unwrap_or_default(), "asksz": q["asksize"].as_u64().unwrap_or_default(),
+        "askexch": q["askexch"].as_str().unwrap_or_default(), "askdate": q["ask_date"].as_u64().unwrap_or_default(),
+    }).to_string()).collect()
+}
 
-use reqwest::Client;
-
-async fn tradier_post(uri: &str) -> Result<String, reqwest::Error> {
-    // TODO: show error message if key missing
-    let api_key = env::var("TRADIER_API_KEY").expect("Required TRADIER_API_KEY environment variable was not found");
-    const BASE_URL: &str = "https://api.tradier.com/v1";
-    let url = [BASE_URL, uri].concat();
-
-    let client = Client::new();
-
-    client
-        .post(url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        // .header("Content-Type", "application/json")
-        .header("Accept", "application/json")
-        .header("Content-Length", 0) // body.len().to_string())
-        .body("")
-        .send()
-        .await?
-        .text()
-        .await
-
-    // match response {
-    //     Ok(res) => Ok(res),
-    //     Err(e) => Err(e),
-    // }
+#[cfg(feature = "stream")]
+fn parse_session_id(resp:&str) -> Option<String> {
+    let data = serde_json::from_str::<Value>(resp).ok()?;
+    data["stream"]["sessionid"].as_str().map(str::to_string)
 }
 
+/// With linebreak enabled in the subscription, a frame can hold several messages separated by newlines.
+#[cfg(feature = "stream")]
+fn split_messages(text:&str) -> impl Iterator<Item = &str> {
+    text.split('\n').map(str::trim).filter(|line| !line.is_empty())
+}
+
+/// Tradier reports an expired or unknown session as a text message with an error field instead of closing.
+#[cfg(feature = "stream")]
+fn is_session_error(payload:&str) -> bool {
+    payload.starts_with("{\"error\"") && payload.to_ascii_lowercase().contains("session")
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::arch::asm;
 
+    #[cfg(feature = "stream")]
     struct Test {
         data:String
     }
 
+    #[cfg(feature = "stream")]
     impl Handler<String> for Test {
-        fn on_data(&mut self, timestamp:NaiveDateTime, data:String) {
+        fn on_data(&mut self, _timestamp:DateTime<Utc>, data:String) {
             // let ago1 = timestamp.elapsed();
             // let ago2 = timestamp.elapsed();
             // let t1 = core::arch::x86::_rdtsc();
@@ -183,21 +640,28 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "stream")]
     #[test]
+    #[ignore = "requires TRADIER_API_KEY and network access"]
     fn test_websocket() {
         let h = Test { data: "none yet".to_string() };
-        start(h, "SPY");
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread().enable_io().enable_time().build().unwrap();
+            rt.block_on(run_async(h, &["SPY"]));
+        });
         std::thread::sleep(std::time::Duration::from_secs(4));
         println!("Test websocket ending");
     }
 
+    #[cfg(feature = "stream")]
     #[tokio::test]
+    #[ignore = "requires TRADIER_API_KEY and network access"]
     async fn test_run_async() {
         // let h = Test { data: "none yet".to_string() };
         // run_sync(h);
         struct HH(u16);
         impl Handler<String> for HH {
-            fn on_data(&mut self, timestamp:NaiveDateTime, data:String) {
+            fn on_data(&mut self, _timestamp:DateTime<Utc>, data:String) {
                 println!("Handler::on_data called, msg received {:?}", data);
                 self.0 += 1;
                 if self.0 > 2 {
@@ -206,11 +670,128 @@ mod tests {
                 }
             }
         }
-        run_async(HH(0), "SPY").await;
+        run_async(HH(0), &["SPY"]).await;
         std::thread::sleep(std::time::Duration::from_secs(4));
         println!("Test run_async ending");
     }
 
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_session_parsing() {
+        assert_eq!(parse_session_id(r#"{"stream":{"url":"https://stream.tradier.com/v1/markets/events","sessionid":"c8638963-a6d4-4fb9-9bc6-e25fbd8c60c3"}}"#).as_deref(),
+            Some("c8638963-a6d4-4fb9-9bc6-e25fbd8c60c3"));
+        assert_eq!(parse_session_id(r#"{"fault":{"faultstring":"Invalid Access Token"}}"#), None);
+        assert!(is_session_error(r#"{"error":"session not found"}"#));
+        assert!(!is_session_error(r#"{"type":"trade","symbol":"SESSION"}"#));
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_split_messages() {
+        let frame = "{\"type\":\"trade\",\"symbol\":\"SPY\"}\n{\"type\":\"quote\",\"symbol\":\"QQQ\"}\r\n\n";
+        assert_eq!(split_messages(frame).collect::<Vec<_>>(), [r#"{"type":"trade","symbol":"SPY"}"#, r#"{"type":"quote","symbol":"QQQ"}"#]);
+        assert_eq!(split_messages(r#"{"type":"trade"}"#).count(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_messages() {
+        let body = r#"{"quotes":{"quote":{"symbol":"XYZ","bid":10.5,"bidsize":2,"bidexch":"Q","bid_date":1557757189000,"ask":10.7,"asksize":3,"askexch":"N","ask_date":1557757190000}}}"#;
+        let msgs = snapshot_messages(body);
+        assert_eq!(msgs.len(), 1);
+        match crate::events::parse_event(&msgs[0]).unwrap() {
+            crate::events::StreamEvent::Quote(q) => {
+                assert!(q.is_snapshot());
+                assert_eq!((q.symbol.as_str(), q.bid, q.ask_size, q.ask_date), ("XYZ", 10.5, 3, 1557757190000));
+            },
+            other => panic!("{:?}", other),
+        }
+        let body = r#"{"quotes":{"quote":[{"symbol":"A","bid":1.0,"ask":1.1},{"symbol":"B","bid":null,"ask":null}],"unmatched_symbols":{"symbol":"ZZZZ"}}}"#;
+        assert_eq!(snapshot_messages(body).len(), 2);
+        assert!(snapshot_messages("not json").is_empty());
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_keepalive() {
+        let start = Instant::now();
+        let secs = |n:u64| start + Duration::from_secs(n);
+        let mut k = Keepalive::new(&StreamConfig::default(), start);
+        assert_eq!(k.check(secs(50)), KeepaliveAction::Wait);
+        assert_eq!(k.check(secs(100)), KeepaliveAction::Ping);
+        assert_eq!(k.deadline(), secs(110));
+        assert_eq!(k.check(secs(110)), KeepaliveAction::Ping);
+        assert_eq!(k.check(secs(120)), KeepaliveAction::Disconnect);
+
+        let mut k = Keepalive::new(&StreamConfig::default(), start);
+        assert_eq!(k.check(secs(100)), KeepaliveAction::Ping);
+        k.received(secs(105));
+        assert_eq!(k.deadline(), secs(205));
+        assert_eq!(k.check(secs(205)), KeepaliveAction::Ping);
+    }
+
+    #[test]
+    fn test_shard_symbols() {
+        assert_eq!(shard_symbols(&["C", "A", "B", "A", "E", "D"], 2), [vec!["A", "B"], vec!["C", "D"], vec!["E"]]);
+        assert_eq!(shard_symbols(&["A", "B", "C", "D", "E"], 4), [vec!["A", "B", "C"], vec!["D", "E"]]);
+        assert_eq!(shard_symbols(&["B", "A"], 10), [vec!["A", "B"]]);
+        assert!(shard_symbols(&[], 10).is_empty());
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_sharded_stream() {
+        #[derive(Clone)]
+        struct Ignore;
+        impl Handler<String> for Ignore {
+            fn on_data(&mut self, _timestamp:DateTime<Utc>, _data:String) {}
+        }
+        // Session creation fails, so each session makes one request then waits to retry.
+        let transport = Arc::new(crate::http::MockTransport::new());
+        let sessions_started = || async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            transport.requests().len()
+        };
+        let mut stream = ShardedStream::new(Ignore, &["E", "C", "A"], 2, StreamConfig::default().transport(transport.clone()));
+        assert_eq!(stream.shards(), [vec!["A", "C"], vec!["E"]]);
+        assert_eq!(sessions_started().await, 2);
+
+        // A new symbol goes into the shard with room, restarting only that one.
+        stream.subscribe(&["B", "A"]);
+        assert_eq!(stream.shards(), [vec!["A", "C"], vec!["E", "B"]]);
+        assert_eq!(sessions_started().await, 3);
+
+        // With no room left, only new shards start.
+        stream.subscribe(&["F", "D", "G"]);
+        assert_eq!(stream.shards(), [vec!["A", "C"], vec!["E", "B"], vec!["D", "F"], vec!["G"]]);
+        assert_eq!(sessions_started().await, 5);
+
+        // Removing restarts the shard that kept a symbol and stops the emptied one.
+        stream.unsubscribe(&["A", "E", "B"]);
+        assert_eq!(stream.symbols(), ["C", "D", "F", "G"]);
+        assert_eq!(stream.shards(), [vec!["C"], vec!["D", "F"], vec!["G"]]);
+        assert_eq!(sessions_started().await, 6);
+
+        stream.subscribe(&["A"]);
+        assert_eq!(stream.shards(), [vec!["C", "A"], vec!["D", "F"], vec!["G"]]);
+        assert_eq!(sessions_started().await, 7);
+
+        stream.unsubscribe(&["A", "C", "D", "F", "G"]);
+        assert!(stream.shards().is_empty());
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_connection_events() {
+        let mut config = StreamConfig::default();
+        config.emit(ConnectionEvent::Connecting);
+        let mut rx = config.connection_events();
+        config.emit(ConnectionEvent::Connected);
+        config.emit(ConnectionEvent::Disconnected { reason: "test".to_string() });
+        assert_eq!(rx.try_recv().unwrap(), ConnectionEvent::Connected);
+        assert_eq!(rx.try_recv().unwrap(), ConnectionEvent::Disconnected { reason: "test".to_string() });
+        assert!(rx.try_recv().is_err());
+    }
+
     #[test]
     fn test_timing() {
         unsafe {
@@ -282,7 +863,7 @@ mod tests {
             let t2 = (t2high as u64) << 32 | t2low as u64;
             println!("Asm combined elapsed {}", t2 - t1);
 
-            println!("time: {}", Utc::now().naive_utc());
+            println!("time: {}", Utc::now());
         }
     }
 }
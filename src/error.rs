@@ -0,0 +1,100 @@
+use reqwest::StatusCode;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Sending the request or reading the response failed.
+    Http(reqwest::Error),
+    /// The named environment variable holding the API key was not set, TRADIER_API_KEY by default.
+    MissingApiKey(String),
+    /// Obtaining or refreshing an OAuth access token failed.
+    Auth(String),
+    /// Creating a streaming session or connecting the websocket failed.
+    Stream(String),
+    /// A symbol failed local validation, see [`crate::symbol::Symbol::parse`].
+    InvalidSymbol(String),
+    /// Tradier answered with a structured error body, see [`crate::http::parse_api_error`].
+    Api(ApiError),
+    /// Any other response with a status outside 2xx, after retries if any.
+    Status { status: StatusCode, body: String },
+    /// A successful response body didn't have the expected shape.
+    Parse(String),
+    /// The arguments were rejected locally before sending anything, eg. a date range that ends before it starts.
+    InvalidRequest(String),
+    /// Reading or writing a local file failed, eg. in [`crate::history::download_to`].
+    Io(std::io::Error),
+}
+
+/// An error reported by Tradier in a `{"fault": ...}` or `{"errors": ...}` response body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub reason: ApiErrorReason,
+    /// The fault string or error messages as sent.
+    pub messages: Vec<String>,
+    /// Machine readable code from the fault detail, eg. `keymanagement.service.invalid_access_token`.
+    pub code: Option<String>,
+}
+
+/// Classification of [`ApiError`] for deciding what to do about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorReason {
+    InvalidToken,
+    UnknownSymbol,
+    MarketClosed,
+    RateLimited,
+    Other,
+}
+
+impl Error {
+    /// True for errors that may succeed if the request is retried: timeouts and connection failures.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Http(e) => e.is_timeout() || e.is_connect(),
+            Error::Api(e) => e.reason == ApiErrorReason::RateLimited,
+            Error::Status { status, .. } => *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            Error::MissingApiKey(_) | Error::Auth(_) | Error::Stream(_) | Error::InvalidSymbol(_) | Error::Parse(_) | Error::InvalidRequest(_)
+                | Error::Io(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "HTTP request failed: {}", e),
+            Error::MissingApiKey(var) => write!(f, "Required {} environment variable was not found", var),
+            Error::Auth(msg) => write!(f, "Authorization failed: {}", msg),
+            Error::Stream(msg) => write!(f, "Streaming failed: {}", msg),
+            Error::InvalidSymbol(msg) => write!(f, "Invalid symbol: {}", msg),
+            Error::Api(e) => write!(f, "Tradier error ({}, {:?}): {}", e.status, e.reason, e.messages.join("; ")),
+            Error::Status { status, body } => write!(f, "Request failed with status {}: {}", status, body),
+            Error::Parse(msg) => write!(f, "Unexpected response: {}", msg),
+            Error::InvalidRequest(msg) => write!(f, "Invalid request: {}", msg),
+            Error::Io(e) => write!(f, "I/O failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::MissingApiKey(_) | Error::Auth(_) | Error::Stream(_) | Error::InvalidSymbol(_) | Error::Api(_) | Error::Status { .. } | Error::Parse(_)
+                | Error::InvalidRequest(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e:reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e:std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,303 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{de::{self, DeserializeOwned}, Deserialize, Deserializer};
+use serde_json::Value;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+use crate::{data::Handler, throttle::warn_throttled, watchdog::FeedHealth};
+
+/// A message from the Tradier market data stream.
+/// See: https://documentation.tradier.com/brokerage-api/streaming/get-markets-events
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StreamEvent {
+    Quote(QuoteEvent),
+    Trade(TradeEvent),
+    /// A trade with the extended fields included.
+    Tradex(TradeEvent),
+    Summary(SummaryEvent),
+    Timesale(TimesaleEvent),
+    /// Any message type not modeled here.
+    #[serde(other)]
+    Other,
+}
+
+impl StreamEvent {
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            StreamEvent::Quote(e) => Some(&e.symbol),
+            StreamEvent::Trade(e) | StreamEvent::Tradex(e) => Some(&e.symbol),
+            StreamEvent::Summary(e) => Some(&e.symbol),
+            StreamEvent::Timesale(e) => Some(&e.symbol),
+            StreamEvent::Other => None,
+        }
+    }
+
+    /// Exchange time of the event, for quotes the later of the bid and ask times. None for summaries.
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            StreamEvent::Quote(e) => e.bid_time().max(e.ask_time()),
+            StreamEvent::Trade(e) | StreamEvent::Tradex(e) => e.time(),
+            StreamEvent::Timesale(e) => e.time(),
+            StreamEvent::Summary(_) | StreamEvent::Other => None,
+        }
+    }
+}
+
+/// Converts the epoch millis Tradier uses in stream events. None for 0, which Tradier sends when unknown.
+pub fn millis_to_utc(millis:u64) -> Option<DateTime<Utc>> {
+    if millis == 0 { None } else { DateTime::from_timestamp_millis(millis as i64) }
+}
+
+/// Dates are epoch millis.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct QuoteEvent {
+    pub symbol: String,
+    #[serde(deserialize_with = "de_num")]
+    pub bid: f64,
+    #[serde(rename = "bidsz", deserialize_with = "de_num")]
+    pub bid_size: u64,
+    #[serde(rename = "bidexch", default)]
+    pub bid_exchange: String,
+    #[serde(rename = "biddate", deserialize_with = "de_num")]
+    pub bid_date: u64,
+    #[serde(deserialize_with = "de_num")]
+    pub ask: f64,
+    #[serde(rename = "asksz", deserialize_with = "de_num")]
+    pub ask_size: u64,
+    #[serde(rename = "askexch", default)]
+    pub ask_exchange: String,
+    #[serde(rename = "askdate", deserialize_with = "de_num")]
+    pub ask_date: u64,
+    /// Fields not modeled above, so new ones Tradier adds are still available.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl QuoteEvent {
+    /// True for quotes fetched over REST on subscribe, see [`crate::data::StreamConfig::snapshot_on_subscribe`].
+    pub fn is_snapshot(&self) -> bool {
+        self.extra.get("snapshot").and_then(Value::as_bool).unwrap_or(false)
+    }
+
+    pub fn bid_time(&self) -> Option<DateTime<Utc>> {
+        millis_to_utc(self.bid_date)
+    }
+
+    pub fn ask_time(&self) -> Option<DateTime<Utc>> {
+        millis_to_utc(self.ask_date)
+    }
+
+    /// Time since the more recent of the bid and ask was set. None if neither time is known.
+    pub fn quote_age(&self, now:DateTime<Utc>) -> Option<Duration> {
+        Some(now - self.bid_time().max(self.ask_time())?)
+    }
+
+    /// True when the quote is older than threshold, or its age is unknown.
+    pub fn is_stale(&self, now:DateTime<Utc>, threshold:Duration) -> bool {
+        self.quote_age(now).map_or(true, |age| age > threshold)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TradeEvent {
+    pub symbol: String,
+    #[serde(rename = "exch", default)]
+    pub exchange: String,
+    #[serde(deserialize_with = "de_num")]
+    pub price: f64,
+    #[serde(deserialize_with = "de_num")]
+    pub size: u64,
+    /// Cumulative volume for the day.
+    #[serde(rename = "cvol", deserialize_with = "de_num")]
+    pub cumulative_volume: u64,
+    #[serde(deserialize_with = "de_num")]
+    pub date: u64,
+    #[serde(deserialize_with = "de_num")]
+    pub last: f64,
+    /// Fields not modeled above, so new ones Tradier adds are still available.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl TradeEvent {
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        millis_to_utc(self.date)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SummaryEvent {
+    pub symbol: String,
+    #[serde(default, deserialize_with = "de_opt_num")]
+    pub open: Option<f64>,
+    #[serde(default, deserialize_with = "de_opt_num")]
+    pub high: Option<f64>,
+    #[serde(default, deserialize_with = "de_opt_num")]
+    pub low: Option<f64>,
+    #[serde(rename = "prevClose", default, deserialize_with = "de_opt_num")]
+    pub prev_close: Option<f64>,
+    #[serde(default, deserialize_with = "de_opt_num")]
+    pub close: Option<f64>,
+    /// Fields not modeled above, so new ones Tradier adds are still available.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TimesaleEvent {
+    pub symbol: String,
+    #[serde(rename = "exch", default)]
+    pub exchange: String,
+    #[serde(deserialize_with = "de_num")]
+    pub bid: f64,
+    #[serde(deserialize_with = "de_num")]
+    pub ask: f64,
+    #[serde(deserialize_with = "de_num")]
+    pub last: f64,
+    #[serde(deserialize_with = "de_num")]
+    pub size: u64,
+    #[serde(deserialize_with = "de_num")]
+    pub date: u64,
+    #[serde(default, deserialize_with = "de_num")]
+    pub seq: u64,
+    #[serde(default)]
+    pub flag: String,
+    #[serde(default)]
+    pub cancel: bool,
+    #[serde(default)]
+    pub correction: bool,
+    #[serde(default)]
+    pub session: String,
+    /// Fields not modeled above, so new ones Tradier adds are still available.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl TimesaleEvent {
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        millis_to_utc(self.date)
+    }
+}
+
+pub fn parse_event(payload:&str) -> Result<StreamEvent, serde_json::Error> {
+    serde_json::from_str(payload)
+}
+
+/// Handler that parses raw stream messages into [`StreamEvent`]s and passes them to the wrapped handler.
+/// Messages that fail to parse are reported as (throttled) warnings and skipped.
+pub struct TypedHandler<H> {
+    inner: H,
+}
+
+impl<H> TypedHandler<H> {
+    pub fn new(inner:H) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<H:Handler<StreamEvent>> Handler<String> for TypedHandler<H> {
+    fn on_data(&mut self, timestamp:DateTime<Utc>, data:String) {
+        match parse_event(&data) {
+            Ok(event) => self.inner.on_data(timestamp, event),
+            Err(e) => warn_throttled("stream:parse", &format!("{}: Error parsing stream message: {} |{}|", timestamp, e, data)),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    fn on_feed_health(&mut self, health:FeedHealth) {
+        self.inner.on_feed_health(health)
+    }
+}
+
+/// Tradier sends some numbers as strings, eg. `"price":"281.85"`, so accept either.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumOrStr<T> {
+    Num(T),
+    Str(String),
+}
+
+fn de_num<'de, D, T>(d:D) -> Result<T, D::Error>
+where D:Deserializer<'de>, T:DeserializeOwned + FromStr, T::Err:Display {
+    match NumOrStr::<T>::deserialize(d)? {
+        NumOrStr::Num(n) => Ok(n),
+        NumOrStr::Str(s) => s.trim().parse().map_err(de::Error::custom),
+    }
+}
+
+/// Like [`de_num`] but null or an empty string is None.
+fn de_opt_num<'de, D, T>(d:D) -> Result<Option<T>, D::Error>
+where D:Deserializer<'de>, T:DeserializeOwned + FromStr, T::Err:Display {
+    match Option::<NumOrStr<T>>::deserialize(d)? {
+        None => Ok(None),
+        Some(NumOrStr::Num(n)) => Ok(Some(n)),
+        Some(NumOrStr::Str(s)) if s.trim().is_empty() => Ok(None),
+        Some(NumOrStr::Str(s)) => s.trim().parse().map(Some).map_err(de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quote() {
+        let e = parse_event(r#"{"type":"quote","symbol":"C","bid":281.84,"bidsz":60,"bidexch":"M","biddate":"1557757189000","ask":281.85,"asksz":6,"askexch":"Z","askdate":"1557757190000"}"#).unwrap();
+        assert_eq!(e, StreamEvent::Quote(QuoteEvent {
+            symbol: "C".to_string(), bid: 281.84, bid_size: 60, bid_exchange: "M".to_string(), bid_date: 1557757189000,
+            ask: 281.85, ask_size: 6, ask_exchange: "Z".to_string(), ask_date: 1557757190000, extra: HashMap::new(),
+        }));
+        assert_eq!(e.time().unwrap().to_rfc3339(), "2019-05-13T14:19:50+00:00");
+        assert_eq!(millis_to_utc(0), None);
+        let StreamEvent::Quote(q) = &e else { unreachable!() };
+        let now = millis_to_utc(1557757195000).unwrap();
+        assert_eq!(q.quote_age(now), Some(Duration::seconds(5)));
+        assert!(!q.is_stale(now, Duration::seconds(5)));
+        assert!(q.is_stale(now, Duration::seconds(4)));
+        assert_eq!(e.symbol(), Some("C"));
+    }
+
+    #[test]
+    fn test_parse_trade() {
+        let e = parse_event(r#"{"type":"trade","symbol":"C","exch":"B","price":"281.85","size":"100","cvol":"30361891","date":"1557757190000","last":"281.85"}"#).unwrap();
+        assert_eq!(e, StreamEvent::Trade(TradeEvent {
+            symbol: "C".to_string(), exchange: "B".to_string(), price: 281.85, size: 100,
+            cumulative_volume: 30361891, date: 1557757190000, last: 281.85, extra: HashMap::new(),
+        }));
+        let e = parse_event(r#"{"type":"tradex","symbol":"C","exch":"Q","price":"281.85","size":"100","cvol":"30361991","date":"1557757190000","last":"281.85"}"#).unwrap();
+        assert!(matches!(e, StreamEvent::Tradex(TradeEvent { cumulative_volume: 30361991, .. })));
+    }
+
+    #[test]
+    fn test_parse_summary_timesale() {
+        let e = parse_event(r#"{"type":"summary","symbol":"C","open":"282.42","high":"283.49","low":"281.09","prevClose":"283.7","close":""}"#).unwrap();
+        assert_eq!(e, StreamEvent::Summary(SummaryEvent {
+            symbol: "C".to_string(), open: Some(282.42), high: Some(283.49), low: Some(281.09), prev_close: Some(283.7), close: None, extra: HashMap::new(),
+        }));
+        let e = parse_event(r#"{"type":"timesale","symbol":"C","exch":"Q","bid":"281.84","ask":"281.85","last":"281.85","size":"100","date":"1557757190000","seq":7025,"flag":"","cancel":false,"correction":false,"session":"normal"}"#).unwrap();
+        assert!(matches!(e, StreamEvent::Timesale(TimesaleEvent { seq: 7025, size: 100, cancel: false, .. })));
+    }
+
+    #[test]
+    fn test_extra_fields() {
+        let e = parse_event(r#"{"type":"summary","symbol":"C","open":"282.42","newField":{"a":1},"flag":"x"}"#).unwrap();
+        let StreamEvent::Summary(summary) = e else { panic!("expected summary") };
+        assert_eq!(summary.open, Some(282.42));
+        assert_eq!(summary.extra.len(), 2);
+        assert_eq!(summary.extra["newField"]["a"], 1);
+        assert!(!summary.extra.contains_key("type"));
+    }
+
+    #[test]
+    fn test_parse_other() {
+        assert_eq!(parse_event(r#"{"type":"heartbeat"}"#).unwrap(), StreamEvent::Other);
+        assert!(parse_event(r#"{"type":"trade","symbol":"C","price":"abc"}"#).is_err());
+        assert!(parse_event("not json").is_err());
+    }
+}
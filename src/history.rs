@@ -0,0 +1,96 @@
+//! Bulk history downloads written straight to a file a year of bars at a time, so memory use doesn't grow with
+//! the range.
+
+use chrono::NaiveDate;
+use serde_json::Value;
+use std::path::Path;
+use tokio::{fs::File, io::{AsyncWriteExt, BufWriter}};
+use crate::{error::Error, http::HttpTransport, markets::{check_range, extend_one_or_many, get_history, get_history_with, year_ranges, HistoryInterval, HistoryOptions}};
+
+/// File format for [`download_to`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFormat {
+    /// A `date,open,high,low,close,volume` header then one row per bar.
+    Csv,
+    /// One bar per line as the JSON object Tradier sent.
+    Jsonl,
+}
+
+/// Writes the bars for the range to a new file at path, replacing any existing one, and returns how many were
+/// written. The range is fetched a year at a time and each year is written before the next is requested.
+/// [`HistoryOptions::adj_close`] isn't supported since back adjustment needs the whole series, use [`get_history`].
+pub async fn download_to<P:AsRef<Path>>(path:P, format:HistoryFormat, symbol:&str, interval:HistoryInterval, start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<usize, Error> {
+    download(None, path.as_ref(), format, symbol, interval, start, end, options).await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn download_to_with<P:AsRef<Path>>(transport:&dyn HttpTransport, path:P, format:HistoryFormat, symbol:&str, interval:HistoryInterval, start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<usize, Error> {
+    download(Some(transport), path.as_ref(), format, symbol, interval, start, end, options).await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn download(transport:Option<&dyn HttpTransport>, path:&Path, format:HistoryFormat, symbol:&str, interval:HistoryInterval, start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<usize, Error> {
+    if options.adj_close.is_some() {
+        return Err(Error::InvalidRequest("adj_close can't be computed a year at a time, use get_history".to_string()));
+    }
+    check_range(start, end)?;
+    let mut out = BufWriter::new(File::create(path).await?);
+    if format == HistoryFormat::Csv {
+        out.write_all(b"date,open,high,low,close,volume\n").await?;
+    }
+    let (mut count, mut last_date) = (0, None);
+    for (start, end) in year_ranges(start, end) {
+        let body = match transport {
+            Some(transport) => get_history_with(transport, symbol, interval, start, end, options).await?,
+            None => get_history(symbol, interval, start, end, options).await?,
+        };
+        let data: Value = serde_json::from_str(&body).map_err(|e| Error::Parse(format!("history: {}", e)))?;
+        let mut days = Vec::new();
+        extend_one_or_many(&mut days, &data["history"]["day"]);
+        for day in days {
+            let date = day["date"].as_str().map(str::to_string);
+            // A weekly or monthly bar starting before a year boundary comes back in both years.
+            if date.is_some() && date <= last_date {
+                continue;
+            }
+            let line = match format {
+                HistoryFormat::Csv => format!("{},{},{},{},{},{}\n", day["date"].as_str().unwrap_or_default(),
+                    day["open"], day["high"], day["low"], day["close"], day["volume"]),
+                HistoryFormat::Jsonl => format!("{}\n", day),
+            };
+            out.write_all(line.as_bytes()).await?;
+            last_date = date;
+            count += 1;
+        }
+    }
+    out.flush().await?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::MockTransport;
+    use reqwest::StatusCode;
+
+    #[tokio::test]
+    async fn test_download_to() {
+        let transport = MockTransport::new()
+            .with_response("/markets/history", StatusCode::OK, r#"{"history":{"day":[{"date":"2022-12-26","open":1.0,"high":2.0,"low":0.5,"close":1.5,"volume":100}]}}"#)
+            .with_response("/markets/history", StatusCode::OK, r#"{"history":{"day":[{"date":"2022-12-26","open":1.0,"high":2.0,"low":0.5,"close":1.5,"volume":100},{"date":"2023-01-02","open":1.5,"high":3.0,"low":1.0,"close":2.5,"volume":200}]}}"#);
+        let (start, end) = (NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(), NaiveDate::from_ymd_opt(2023, 6, 30).unwrap());
+        let path = std::env::temp_dir().join(format!("rust-tradier-history-{}.csv", std::process::id()));
+        let written = download_to_with(&transport, &path, HistoryFormat::Csv, "SPY", HistoryInterval::Weekly, start, end, &HistoryOptions::default()).await.unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "date,open,high,low,close,volume\n2022-12-26,1.0,2.0,0.5,1.5,100\n2023-01-02,1.5,3.0,1.0,2.5,200\n");
+        assert_eq!(transport.requests().len(), 2);
+
+        let written = download_to_with(&transport, &path, HistoryFormat::Jsonl, "SPY", HistoryInterval::Weekly, end, end, &HistoryOptions::default()).await.unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().next().unwrap(), r#"{"close":1.5,"date":"2022-12-26","high":2.0,"low":0.5,"open":1.0,"volume":100}"#);
+        std::fs::remove_file(&path).unwrap();
+
+        let options = HistoryOptions { adj_close: Some(Vec::new()), ..Default::default() };
+        assert!(matches!(download_to_with(&transport, &path, HistoryFormat::Csv, "SPY", HistoryInterval::Daily, start, end, &options).await, Err(Error::InvalidRequest(_))));
+    }
+}
@@ -0,0 +1,907 @@
+use std::{collections::{HashMap, VecDeque}, path::PathBuf, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, OnceLock, RwLock}, time::Duration};
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use reqwest::{header::{HeaderMap, HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED}, Client, Method, RequestBuilder, StatusCode};
+use tokio::runtime::{Builder, Runtime};
+use serde_json::{json, Value};
+use crate::{auth::{Credentials, TokenProvider}, error::{ApiError, ApiErrorReason, Error}, net::NetworkConfig, throttle::warn_throttled};
+
+const BASE_URL: &str = "https://api.tradier.com";
+/// Upper bound on the delay between retries however many there are.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    /// Used by the fundamentals endpoints.
+    Beta,
+}
+
+impl ApiVersion {
+    pub fn path(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/v1",
+            ApiVersion::Beta => "/beta",
+        }
+    }
+}
+
+/// Retries are made for connection errors, timeouts, 429 and 5xx responses.
+/// Only idempotent requests (GET, HEAD, OPTIONS) are retried unless [`RequestOptions::retry_mutations`] is set,
+/// since eg. a timed out order submission may have gone through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each retry after that up to [`MAX_BACKOFF`].
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub const NONE: RetryPolicy = RetryPolicy { max_retries: 0, backoff: Duration::ZERO };
+
+    /// Delay before retry number attempt, counting from 0.
+    pub fn delay(&self, attempt:u32) -> Duration {
+        self.backoff.saturating_mul(2u32.checked_pow(attempt).unwrap_or(u32::MAX)).min(MAX_BACKOFF)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_retries: 2, backoff: Duration::from_millis(500) }
+    }
+}
+
+/// Per call options for [`tradier_request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestOptions {
+    pub version: ApiVersion,
+    pub timeout: Option<Duration>,
+    pub retry: RetryPolicy,
+    pub accept: &'static str,
+    /// Also retry POST, PUT, DELETE and PATCH. Only safe when sending the request twice can't do harm.
+    pub retry_mutations: bool,
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        RequestOptions { version: ApiVersion::V1, timeout: None, retry: RetryPolicy::default(), accept: "application/json", retry_mutations: false }
+    }
+}
+
+impl RequestOptions {
+    pub fn version(mut self, version:ApiVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn timeout(mut self, timeout:Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn retry(mut self, retry:RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn accept(mut self, accept:&'static str) -> Self {
+        self.accept = accept;
+        self
+    }
+
+    pub fn retry_mutations(mut self, retry_mutations:bool) -> Self {
+        self.retry_mutations = retry_mutations;
+        self
+    }
+}
+
+pub async fn tradier_get(uri:&str) -> Result<String, Error> {
+    tradier_request(Method::GET, uri, &RequestOptions::default()).await
+}
+
+pub async fn tradier_post(uri:&str) -> Result<String, Error> {
+    tradier_request(Method::POST, uri, &RequestOptions::default()).await
+}
+
+/// uri is the path after the version, including any query string, eg. `/markets/quotes?symbols=SPY`.
+/// Returns the response body. Structured Tradier error bodies are returned as [`Error::Api`], and any other
+/// response with a status outside 2xx as [`Error::Status`].
+/// Requests are sent through the transport set with [`set_transport`], by default [`ReqwestTransport`].
+pub async fn tradier_request(method:Method, uri:&str, opts:&RequestOptions) -> Result<String, Error> {
+    tradier_request_with(&*transport(), method, uri, opts).await
+}
+
+/// Same as [`tradier_request`] but sends through the given transport.
+pub async fn tradier_request_with(transport:&dyn HttpTransport, method:Method, uri:&str, opts:&RequestOptions) -> Result<String, Error> {
+    Ok(send_with_retry(transport, method, uri, opts).await?.body)
+}
+
+pub async fn tradier_get_with_meta(uri:&str) -> Result<(String, ResponseMeta), Error> {
+    tradier_request_with_meta(Method::GET, uri, &RequestOptions::default()).await
+}
+
+pub async fn tradier_post_with_meta(uri:&str) -> Result<(String, ResponseMeta), Error> {
+    tradier_request_with_meta(Method::POST, uri, &RequestOptions::default()).await
+}
+
+/// Same as [`tradier_request`] but also returns the rate limit and request id from the response headers.
+pub async fn tradier_request_with_meta(method:Method, uri:&str, opts:&RequestOptions) -> Result<(String, ResponseMeta), Error> {
+    tradier_request_with_meta_with(&*transport(), method, uri, opts).await
+}
+
+/// Same as [`tradier_request_with_meta`] but sends through the given transport.
+pub async fn tradier_request_with_meta_with(transport:&dyn HttpTransport, method:Method, uri:&str, opts:&RequestOptions) -> Result<(String, ResponseMeta), Error> {
+    let resp = send_with_retry(transport, method, uri, opts).await?;
+    let meta = ResponseMeta { status: resp.status, ..ResponseMeta::from_headers(&resp.headers) };
+    Ok((resp.body, meta))
+}
+
+async fn send_with_retry(transport:&dyn HttpTransport, method:Method, uri:&str, opts:&RequestOptions) -> Result<HttpResponse, Error> {
+    let req = HttpRequest { method, version: opts.version, uri: uri.to_string(), accept: opts.accept, timeout: opts.timeout, headers: HeaderMap::new() };
+
+    let idempotent = matches!(req.method, Method::GET | Method::HEAD | Method::OPTIONS);
+    let max_retries = if idempotent || opts.retry_mutations { opts.retry.max_retries } else { 0 };
+
+    let mut attempt = 0;
+    loop {
+        let retry = attempt < max_retries;
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let result = transport.send(&req).await;
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_rest(req.path(), started.elapsed());
+        match result {
+            Ok(resp) if retry && is_retryable_status(resp.status) => {
+                warn_throttled(&format!("retry:{}", req.path()), &format!("Retrying {} {} after status {}", req.method, uri, resp.status));
+            },
+            Ok(resp) => return match parse_api_error(resp.status, &resp.body) {
+                Some(e) => Err(Error::Api(e)),
+                None if !resp.status.is_success() => Err(Error::Status { status: resp.status, body: resp.body }),
+                None => Ok(resp),
+            },
+            Err(e) if retry && e.is_transient() => {
+                warn_throttled(&format!("retry:{}", req.path()), &format!("Retrying {} {} after error: {}", req.method, uri, e));
+            },
+            Err(e) => return Err(e),
+        }
+        tokio::time::sleep(opts.retry.delay(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// path with the params form encoded as its query string, eg. `/markets/quotes?symbols=SPY%2CQQQ`.
+pub fn query_uri(path:&str, params:&[(&str, &str)]) -> String {
+    let query: String = reqwest::Url::parse_with_params("http://localhost", params)
+        .map(|url| url.query().unwrap_or_default().to_string()).unwrap_or_default();
+    if query.is_empty() { path.to_string() } else { format!("{}?{}", path, query) }
+}
+
+/// Parses Tradier's error bodies: `{"fault":{"faultstring":"...","detail":{"errorcode":"..."}}}`
+/// and `{"errors":{"error":"..."}}` where error may also be a list. None for anything else.
+pub fn parse_api_error(status:StatusCode, body:&str) -> Option<ApiError> {
+    let data = serde_json::from_str::<Value>(body).ok()?;
+    let (messages, code) = if let Some(fault) = data.get("fault") {
+        let message = fault["faultstring"].as_str().unwrap_or_default().to_string();
+        (vec![message], fault["detail"]["errorcode"].as_str().map(str::to_string))
+    } else {
+        let messages = match &data.get("errors")?["error"] {
+            Value::String(s) => vec![s.clone()],
+            Value::Array(list) => list.iter().map(|v| v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string())).collect(),
+            _ => return None,
+        };
+        (messages, None)
+    };
+    let text = format!("{} {}", messages.join(" "), code.as_deref().unwrap_or_default()).to_lowercase();
+    let reason = if status == StatusCode::UNAUTHORIZED || text.contains("access_token") || text.contains("access token") {
+        ApiErrorReason::InvalidToken
+    } else if status == StatusCode::TOO_MANY_REQUESTS || text.contains("quota") || text.contains("rate limit") {
+        ApiErrorReason::RateLimited
+    } else if text.contains("market is closed") || text.contains("market closed") {
+        ApiErrorReason::MarketClosed
+    } else if text.contains("symbol") {
+        ApiErrorReason::UnknownSymbol
+    } else {
+        ApiErrorReason::Other
+    };
+    Some(ApiError { status, reason, messages, code })
+}
+
+/// Metadata from the response status and headers. Fields are None when the header wasn't present or couldn't be parsed.
+/// See: https://documentation.tradier.com/brokerage-api/overview/rate-limiting
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// Always 2xx, since other statuses are returned as errors.
+    pub status: StatusCode,
+    pub rate_limit: Option<RateLimit>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests allowed in the current window.
+    pub allowed: u32,
+    pub used: u32,
+    pub available: u32,
+    /// When the current window resets.
+    pub expiry: DateTime<Utc>,
+}
+
+impl ResponseMeta {
+    pub fn from_headers(headers:&HeaderMap) -> Self {
+        let header = |name:&str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+        let count = |name:&str| header(name).and_then(|v| v.parse::<u32>().ok());
+        let rate_limit = (|| Some(RateLimit {
+            allowed: count("x-ratelimit-allowed")?,
+            used: count("x-ratelimit-used")?,
+            available: count("x-ratelimit-available")?,
+            expiry: DateTime::from_timestamp_millis(header("x-ratelimit-expiry")?.parse::<i64>().ok()?)?,
+        }))();
+        ResponseMeta { status: StatusCode::OK, rate_limit, request_id: header("x-request-id").map(str::to_string) }
+    }
+}
+
+/// A request as seen by an [`HttpTransport`]. Authentication is left to the transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub version: ApiVersion,
+    /// Path after the version, including any query string.
+    pub uri: String,
+    pub accept: &'static str,
+    pub timeout: Option<Duration>,
+    /// Extra headers to send, eg. added by [`ConditionalTransport`].
+    pub headers: HeaderMap,
+}
+
+impl HttpRequest {
+    /// The uri without the query string.
+    pub fn path(&self) -> &str {
+        self.uri.split('?').next().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn new(status:StatusCode, body:&str) -> Self {
+        HttpResponse { status, headers: HeaderMap::new(), body: body.to_string() }
+    }
+}
+
+/// Sends requests to Tradier. Implemented by [`ReqwestTransport`] for real calls and [`MockTransport`] for tests.
+pub trait HttpTransport: Send + Sync {
+    fn send<'a>(&'a self, req:&'a HttpRequest) -> BoxFuture<'a, Result<HttpResponse, Error>>;
+}
+
+/// Sets the transport used by all REST calls in this crate, including streaming session creation.
+pub fn set_transport(transport:Arc<dyn HttpTransport>) {
+    *transport_lock().write().unwrap() = transport;
+}
+
+/// Shorthand for setting a [`ReqwestTransport`] using the given credentials.
+pub fn set_credentials(credentials:Credentials) {
+    set_transport(Arc::new(ReqwestTransport::new(Arc::new(credentials))));
+}
+
+fn transport() -> Arc<dyn HttpTransport> {
+    transport_lock().read().unwrap().clone()
+}
+
+fn transport_lock() -> &'static RwLock<Arc<dyn HttpTransport>> {
+    static TRANSPORT: OnceLock<RwLock<Arc<dyn HttpTransport>>> = OnceLock::new();
+    TRANSPORT.get_or_init(|| RwLock::new(Arc::new(ReqwestTransport::default())))
+}
+
+/// Time allowed to establish a connection, used by [`ReqwestTransport::new`].
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time allowed for a whole request, used by [`ReqwestTransport::new`]. A request's [`RequestOptions::timeout`] replaces it.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends requests to the Tradier API, authenticating with the token from its [`TokenProvider`],
+/// by default the TRADIER_API_KEY environment variable.
+/// A request rejected as unauthorized is retried once after invalidating the token, so providers that can refresh get the chance.
+/// Connections are pooled by the client, so keep one transport rather than creating one per call.
+/// Requests are sent from an internal runtime, see [`send_request`], so a transport can be used from any runtime.
+#[derive(Clone)]
+pub struct ReqwestTransport {
+    tokens: Arc<dyn TokenProvider>,
+    client: Client,
+    base_url: String,
+}
+
+impl ReqwestTransport {
+    /// Uses the client shared by all default transports, with [`DEFAULT_CONNECT_TIMEOUT`] and [`DEFAULT_REQUEST_TIMEOUT`].
+    pub fn new(tokens:Arc<dyn TokenProvider>) -> Self {
+        Self::with_client(tokens, shared_client().clone())
+    }
+
+    /// Uses a separate client with its own connection pool.
+    pub fn with_timeouts(tokens:Arc<dyn TokenProvider>, connect:Duration, request:Duration) -> Self {
+        Self::with_client(tokens, build_client(connect, request))
+    }
+
+    /// Uses a separate client with the default timeouts, connecting through the proxy and trusting the root
+    /// certificates in network. Fails if the proxy url or a certificate is invalid.
+    pub fn with_network(tokens:Arc<dyn TokenProvider>, network:&NetworkConfig) -> Result<Self, Error> {
+        Ok(Self::with_client(tokens, network.build_client(DEFAULT_CONNECT_TIMEOUT, DEFAULT_REQUEST_TIMEOUT)?))
+    }
+
+    /// Uses the given client as is, for full control over its configuration.
+    pub fn with_client(tokens:Arc<dyn TokenProvider>, client:Client) -> Self {
+        Self { tokens, client, base_url: BASE_URL.to_string() }
+    }
+
+    /// Sends to another server instead of `https://api.tradier.com`, eg. `https://sandbox.tradier.com`.
+    pub fn base_url(mut self, url:&str) -> Self {
+        self.base_url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    async fn send_once(&self, req:&HttpRequest) -> Result<HttpResponse, Error> {
+        let api_key = self.tokens.token().await?;
+        let url = [&self.base_url, req.version.path(), &req.uri].concat();
+
+        let mut builder = self.client
+            .request(req.method.clone(), url)
+            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Accept", req.accept)
+            .headers(req.headers.clone());
+        if req.method == Method::POST {
+            builder = builder.header("Content-Length", 0).body("");
+        }
+        if let Some(timeout) = req.timeout {
+            builder = builder.timeout(timeout);
+        }
+        send_request(builder).await
+    }
+}
+
+/// Sends the request on an internal runtime and reads the response there.
+/// A pooled connection is driven by a task on the runtime that opened it, so if callers' runtimes opened them,
+/// a connection opened by a runtime that is now idle or dropped would stall or fail the next request to reuse it,
+/// eg. after a blocking call or a finished `#[tokio::test]`. Dropping the returned future cancels the request.
+pub(crate) async fn send_request(builder:RequestBuilder) -> Result<HttpResponse, Error> {
+    struct AbortOnDrop(tokio::task::AbortHandle);
+    impl Drop for AbortOnDrop {
+        fn drop(&mut self) {
+            self.0.abort();
+        }
+    }
+
+    let task = io_runtime().spawn(async move {
+        let resp = builder.send().await?;
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = resp.text().await?;
+        Ok(HttpResponse { status, headers, body })
+    });
+    let _abort = AbortOnDrop(task.abort_handle());
+    task.await.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+fn io_runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Builder::new_multi_thread().worker_threads(1).thread_name("rust-tradier-http").enable_all().build()
+        .expect("Failed to create tokio runtime"))
+}
+
+/// The client behind [`ReqwestTransport::new`] and the OAuth token requests, created on first use.
+/// Cloning it is cheap and shares its connection pool and TLS sessions. Only send with it through
+/// [`send_request`], so every pooled connection belongs to the same runtime.
+pub(crate) fn shared_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| build_client(DEFAULT_CONNECT_TIMEOUT, DEFAULT_REQUEST_TIMEOUT))
+}
+
+/// Panics if the client can't be created, the same as [`Client::new`].
+fn build_client(connect:Duration, request:Duration) -> Client {
+    Client::builder().connect_timeout(connect).timeout(request).build().expect("Failed to create HTTP client")
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        Self::new(Arc::new(Credentials::default()))
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn send<'a>(&'a self, req:&'a HttpRequest) -> BoxFuture<'a, Result<HttpResponse, Error>> {
+        Box::pin(async move {
+            let resp = self.send_once(req).await?;
+            if resp.status != StatusCode::UNAUTHORIZED {
+                return Ok(resp);
+            }
+            self.tokens.invalidate();
+            self.send_once(req).await
+        })
+    }
+}
+
+/// Transport that returns canned responses keyed by path (the uri without query string) and records all requests.
+/// If several responses are added for a path they are returned in order, with the last one repeating.
+/// Paths without a response get a 404.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: Mutex<HashMap<String, VecDeque<HttpResponse>>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_response(self, path:&str, status:StatusCode, body:&str) -> Self {
+        self.add_response(path, HttpResponse::new(status, body));
+        self
+    }
+
+    pub fn add_response(&self, path:&str, resp:HttpResponse) {
+        self.responses.lock().unwrap().entry(path.to_string()).or_default().push_back(resp);
+    }
+
+    /// All requests sent so far, in order.
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl HttpTransport for MockTransport {
+    fn send<'a>(&'a self, req:&'a HttpRequest) -> BoxFuture<'a, Result<HttpResponse, Error>> {
+        self.requests.lock().unwrap().push(req.clone());
+        let mut responses = self.responses.lock().unwrap();
+        let resp = match responses.get_mut(req.path()) {
+            Some(queue) if queue.len() > 1 => queue.pop_front().unwrap(),
+            Some(queue) if !queue.is_empty() => queue[0].clone(),
+            _ => HttpResponse::new(StatusCode::NOT_FOUND, &format!("No mock response for {}", req.path())),
+        };
+        Box::pin(async move { Ok(resp) })
+    }
+}
+
+/// Wraps a transport, passing through everything except order placement, modification and cancellation,
+/// which are logged and answered with a simulated confirmation instead of being sent.
+/// Use with [`set_transport`] to run a strategy against production data without placing orders.
+#[derive(Debug)]
+pub struct DryRunTransport<T> {
+    inner: T,
+    next_id: AtomicU64,
+}
+
+impl<T:HttpTransport> DryRunTransport<T> {
+    pub fn new(inner:T) -> Self {
+        Self { inner, next_id: AtomicU64::new(1) }
+    }
+}
+
+impl<T:HttpTransport> HttpTransport for DryRunTransport<T> {
+    fn send<'a>(&'a self, req:&'a HttpRequest) -> BoxFuture<'a, Result<HttpResponse, Error>> {
+        if !is_order_mutation(req) {
+            return self.inner.send(req);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        println!("Dry run: not sending {} {}, simulated order id {}", req.method, req.uri, id);
+        let body = json!({ "order": { "id": id, "status": "ok", "dry_run": true } }).to_string();
+        Box::pin(async move { Ok(HttpResponse::new(StatusCode::OK, &body)) })
+    }
+}
+
+/// Record/replay transport for tests against real response shapes. Responses are read from a JSON file per request
+/// in dir, see [`FixtureTransport::fixture_path`]. When there's no file, the request is sent through inner
+/// and a 2xx response saved for next time, others are passed through unsaved. Delete a file to re-record it.
+#[derive(Debug)]
+pub struct FixtureTransport<T> {
+    inner: T,
+    dir: PathBuf,
+}
+
+impl<T:HttpTransport> FixtureTransport<T> {
+    pub fn new<P:Into<PathBuf>>(dir:P, inner:T) -> Self {
+        Self { inner, dir: dir.into() }
+    }
+
+    /// The file for a request: a readable prefix from the method and path, then a hash of the method, version, path
+    /// and query with its parameters sorted, so reordered parameters share a file and distinct queries never do,
+    /// eg. `GET_markets_quotes-3f2a9c0d1e4b5a6f.json`.
+    pub fn fixture_path(&self, req:&HttpRequest) -> PathBuf {
+        let mut params: Vec<(String, String)> = reqwest::Url::parse(&format!("http://localhost{}", req.uri))
+            .map(|url| url.query_pairs().into_owned().collect()).unwrap_or_default();
+        params.sort();
+        let params: Vec<(&str, &str)> = params.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        let key = format!("{} {}{}", req.method, req.version.path(), query_uri(req.path(), &params));
+        let prefix: String = format!("{} {}", req.method, req.path().trim_matches('/')).chars().take(60)
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' }).collect();
+        self.dir.join(format!("{}-{:016x}.json", prefix, fnv1a(key.as_bytes())))
+    }
+}
+
+impl<T:HttpTransport> HttpTransport for FixtureTransport<T> {
+    fn send<'a>(&'a self, req:&'a HttpRequest) -> BoxFuture<'a, Result<HttpResponse, Error>> {
+        Box::pin(async move {
+            let path = self.fixture_path(req);
+            if let Ok(saved) = tokio::fs::read_to_string(&path).await {
+                match parse_fixture(&saved) {
+                    Some(resp) => return Ok(resp),
+                    None => println!("Invalid fixture file {}, recording it again", path.display()),
+                }
+            }
+            let resp = self.inner.send(req).await?;
+            // Errors such as a 429 or an expired token aren't worth replaying, the next run should try again.
+            if !resp.status.is_success() {
+                return Ok(resp);
+            }
+            let headers = resp.headers.iter()
+                .filter_map(|(name, value)| Some((name.to_string(), Value::String(value.to_str().ok()?.to_string()))))
+                .collect::<serde_json::Map<_, _>>();
+            let saved = json!({ "status": resp.status.as_u16(), "headers": headers, "body": resp.body });
+            let written = match tokio::fs::create_dir_all(&self.dir).await {
+                Ok(()) => tokio::fs::write(&path, format!("{:#}", saved)).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                println!("Error saving fixture {}: {}", path.display(), e);
+            }
+            Ok(resp)
+        })
+    }
+}
+
+/// 64 bit FNV-1a, for names that must stay the same across Rust versions, unlike [`std::hash::DefaultHasher`].
+fn fnv1a(data:&[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Caches GET responses that carry an ETag or Last-Modified header and revalidates them with If-None-Match or
+/// If-Modified-Since, returning the cached response on 304 Not Modified. Meant for data that rarely changes,
+/// eg. expirations, the market calendar and easy to borrow lists, to save bandwidth and rate limit.
+/// Responses without validators, and servers that ignore them, work the same as without this wrapper.
+/// At most capacity responses are kept, evicting the least recently used.
+#[derive(Debug)]
+pub struct ConditionalTransport<T> {
+    inner: T,
+    capacity: usize,
+    cache: Mutex<ResponseCache>,
+}
+
+/// Capacity used by [`ConditionalTransport::new`].
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+impl<T:HttpTransport> ConditionalTransport<T> {
+    pub fn new(inner:T) -> Self {
+        Self::with_capacity(inner, DEFAULT_CACHE_CAPACITY)
+    }
+
+    pub fn with_capacity(inner:T, capacity:usize) -> Self {
+        Self { inner, capacity: capacity.max(1), cache: Mutex::new(ResponseCache::default()) }
+    }
+
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().entries.clear();
+    }
+
+    /// Number of cached responses.
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Responses by key with the tick they were last used, for least recently used eviction.
+/// Eviction scans all entries, which is cheap at the sizes this is meant for.
+#[derive(Debug, Default)]
+struct ResponseCache {
+    entries: HashMap<String, (HttpResponse, u64)>,
+    tick: u64,
+}
+
+impl ResponseCache {
+    fn get(&mut self, key:&str) -> Option<HttpResponse> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries.get_mut(key).map(|(resp, used)| {
+            *used = tick;
+            resp.clone()
+        })
+    }
+
+    fn insert(&mut self, key:String, resp:HttpResponse, capacity:usize) {
+        self.tick += 1;
+        if !self.entries.contains_key(&key) && self.entries.len() >= capacity {
+            let oldest = self.entries.iter().min_by_key(|(_, (_, used))| *used).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (resp, self.tick));
+    }
+}
+
+impl<T:HttpTransport> HttpTransport for ConditionalTransport<T> {
+    fn send<'a>(&'a self, req:&'a HttpRequest) -> BoxFuture<'a, Result<HttpResponse, Error>> {
+        if req.method != Method::GET {
+            return self.inner.send(req);
+        }
+        Box::pin(async move {
+            let key = format!("{}{}", req.version.path(), req.uri);
+            let cached = self.cache.lock().unwrap().get(&key);
+            let mut conditional = req.clone();
+            if let Some(cached) = &cached {
+                if let Some(etag) = cached.headers.get(ETAG) {
+                    conditional.headers.insert(IF_NONE_MATCH, etag.clone());
+                }
+                if let Some(modified) = cached.headers.get(LAST_MODIFIED) {
+                    conditional.headers.insert(IF_MODIFIED_SINCE, modified.clone());
+                }
+            }
+            let resp = self.inner.send(&conditional).await?;
+            if let (StatusCode::NOT_MODIFIED, Some(cached)) = (resp.status, cached) {
+                return Ok(cached);
+            }
+            if resp.status == StatusCode::OK && (resp.headers.contains_key(ETAG) || resp.headers.contains_key(LAST_MODIFIED)) {
+                self.cache.lock().unwrap().insert(key, resp.clone(), self.capacity);
+            }
+            Ok(resp)
+        })
+    }
+}
+
+fn parse_fixture(saved:&str) -> Option<HttpResponse> {
+    let saved = serde_json::from_str::<Value>(saved).ok()?;
+    let status = StatusCode::from_u16(u16::try_from(saved["status"].as_u64()?).ok()?).ok()?;
+    let mut resp = HttpResponse::new(status, saved["body"].as_str()?);
+    for (name, value) in saved["headers"].as_object()? {
+        if let (Ok(name), Some(Ok(value))) = (name.parse::<HeaderName>(), value.as_str().map(str::parse)) {
+            resp.headers.insert(name, value);
+        }
+    }
+    Some(resp)
+}
+
+/// Anything other than a GET to `/accounts/{account_id}/orders...`.
+fn is_order_mutation(req:&HttpRequest) -> bool {
+    let mut parts = req.path().trim_start_matches('/').split('/');
+    req.method != Method::GET && parts.next() == Some("accounts") && parts.nth(1) == Some("orders")
+}
+
+fn is_retryable_status(status:StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_options() {
+        let opts = RequestOptions::default().version(ApiVersion::Beta).timeout(Duration::from_secs(5)).retry(RetryPolicy::NONE);
+        assert_eq!([BASE_URL, opts.version.path(), "/markets/fundamentals/dividends"].concat(),
+            "https://api.tradier.com/beta/markets/fundamentals/dividends");
+        assert_eq!(opts.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(opts.retry.max_retries, 0);
+        assert_eq!(opts.accept, "application/json");
+    }
+
+    #[test]
+    fn test_retryable_status() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(StatusCode::UNAUTHORIZED));
+        assert!(!is_retryable_status(StatusCode::OK));
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport() {
+        let mock = MockTransport::new()
+            .with_response("/markets/events/session", StatusCode::OK, r#"{"stream":{"sessionid":"abc"}}"#);
+        let body = tradier_request_with(&mock, Method::POST, "/markets/events/session", &RequestOptions::default()).await.unwrap();
+        assert_eq!(body, r#"{"stream":{"sessionid":"abc"}}"#);
+
+        match tradier_request_with(&mock, Method::GET, "/markets/quotes?symbols=SPY", &RequestOptions::default().retry(RetryPolicy::NONE)).await {
+            Err(Error::Status { status, body }) => assert_eq!((status, body.as_str()), (StatusCode::NOT_FOUND, "No mock response for /markets/quotes")),
+            other => panic!("{:?}", other),
+        }
+
+        let reqs = mock.requests();
+        assert_eq!(reqs.len(), 2);
+        assert_eq!(reqs[0].method, Method::POST);
+        assert_eq!(reqs[1].uri, "/markets/quotes?symbols=SPY");
+    }
+
+    #[tokio::test]
+    async fn test_api_error() {
+        let fault = r#"{"fault":{"faultstring":"Invalid access token","detail":{"errorcode":"keymanagement.service.invalid_access_token"}}}"#;
+        let mock = MockTransport::new().with_response("/markets/quotes", StatusCode::UNAUTHORIZED, fault);
+        match tradier_request_with(&mock, Method::GET, "/markets/quotes?symbols=SPY", &RequestOptions::default()).await {
+            Err(Error::Api(e)) => {
+                assert_eq!((e.status, e.reason), (StatusCode::UNAUTHORIZED, ApiErrorReason::InvalidToken));
+                assert_eq!(e.code.as_deref(), Some("keymanagement.service.invalid_access_token"));
+            },
+            other => panic!("{:?}", other),
+        }
+
+        let e = parse_api_error(StatusCode::BAD_REQUEST, r#"{"errors":{"error":["Invalid Parameter: symbols XYZQ"]}}"#).unwrap();
+        assert_eq!((e.reason, e.messages.len()), (ApiErrorReason::UnknownSymbol, 1));
+        let e = parse_api_error(StatusCode::BAD_REQUEST, r#"{"errors":{"error":"Market is closed."}}"#).unwrap();
+        assert_eq!(e.reason, ApiErrorReason::MarketClosed);
+        assert!(parse_api_error(StatusCode::OK, r#"{"quotes":{}}"#).is_none());
+        assert!(parse_api_error(StatusCode::BAD_GATEWAY, "Bad Gateway").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let mock = MockTransport::new()
+            .with_response("/markets/clock", StatusCode::SERVICE_UNAVAILABLE, "down")
+            .with_response("/markets/clock", StatusCode::OK, "up");
+        let opts = RequestOptions::default().retry(RetryPolicy { max_retries: 1, backoff: Duration::ZERO });
+        assert_eq!(tradier_request_with(&mock, Method::GET, "/markets/clock", &opts).await.unwrap(), "up");
+        assert_eq!(mock.requests().len(), 2);
+
+        let mock = MockTransport::new().with_response("/markets/clock", StatusCode::SERVICE_UNAVAILABLE, "down");
+        match tradier_request_with(&mock, Method::GET, "/markets/clock", &opts).await {
+            Err(e @ Error::Status { .. }) => assert!(e.is_transient()),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(40), MAX_BACKOFF);
+        assert_eq!(RetryPolicy { max_retries: u32::MAX, backoff: Duration::MAX }.delay(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_for_mutations() {
+        let mock = MockTransport::new().with_response("/accounts/VA123/orders", StatusCode::SERVICE_UNAVAILABLE, "down");
+        let opts = RequestOptions::default().retry(RetryPolicy { max_retries: 2, backoff: Duration::ZERO });
+        let _ = tradier_request_with(&mock, Method::POST, "/accounts/VA123/orders", &opts).await;
+        assert_eq!(mock.requests().len(), 1);
+
+        let _ = tradier_request_with(&mock, Method::POST, "/accounts/VA123/orders", &opts.retry_mutations(true)).await;
+        assert_eq!(mock.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let mock = MockTransport::new()
+            .with_response("/accounts/VA123/orders", StatusCode::OK, "real orders")
+            .with_response("/markets/events/session", StatusCode::OK, "session");
+        let dry = DryRunTransport::new(mock);
+        let opts = RequestOptions::default();
+
+        assert_eq!(tradier_request_with(&dry, Method::GET, "/accounts/VA123/orders", &opts).await.unwrap(), "real orders");
+        assert_eq!(tradier_request_with(&dry, Method::POST, "/markets/events/session", &opts).await.unwrap(), "session");
+        let placed = tradier_request_with(&dry, Method::POST, "/accounts/VA123/orders", &opts).await.unwrap();
+        assert_eq!(placed, r#"{"order":{"dry_run":true,"id":1,"status":"ok"}}"#);
+        let cancelled = tradier_request_with(&dry, Method::DELETE, "/accounts/VA123/orders/1", &opts).await.unwrap();
+        assert_eq!(cancelled, r#"{"order":{"dry_run":true,"id":2,"status":"ok"}}"#);
+        assert_eq!(dry.inner.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fixture_transport() {
+        let dir = std::env::temp_dir().join(format!("rust-tradier-fixtures-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut resp = HttpResponse::new(StatusCode::OK, r#"{"quotes":{}}"#);
+        resp.headers.insert("X-Request-Id", "req-1".parse().unwrap());
+        let mock = MockTransport::new();
+        mock.add_response("/markets/quotes", resp);
+        let opts = RequestOptions::default();
+
+        let recording = FixtureTransport::new(&dir, mock);
+        let (body, _) = tradier_request_with_meta_with(&recording, Method::GET, "/markets/quotes?symbols=SPY", &opts).await.unwrap();
+        assert_eq!(body, r#"{"quotes":{}}"#);
+        assert_eq!(recording.inner.requests().len(), 1);
+
+        let replaying = FixtureTransport::new(&dir, MockTransport::new());
+        let (body, meta) = tradier_request_with_meta_with(&replaying, Method::GET, "/markets/quotes?symbols=SPY", &opts).await.unwrap();
+        assert_eq!(body, r#"{"quotes":{}}"#);
+        assert_eq!(meta.request_id.as_deref(), Some("req-1"));
+        assert!(replaying.inner.requests().is_empty());
+        let other = tradier_request_with(&replaying, Method::GET, "/markets/quotes?symbols=QQQ", &opts.clone().retry(RetryPolicy::NONE)).await;
+        assert!(matches!(other, Err(Error::Status { status: StatusCode::NOT_FOUND, .. })));
+        // The 404 wasn't saved, so the request is sent again.
+        let other = tradier_request_with(&replaying, Method::GET, "/markets/quotes?symbols=QQQ", &opts.retry(RetryPolicy::NONE)).await;
+        assert!(matches!(other, Err(Error::Status { status: StatusCode::NOT_FOUND, .. })));
+        assert_eq!(replaying.inner.requests().len(), 2);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fixture_path() {
+        let fixtures = FixtureTransport::new("fixtures", MockTransport::new());
+        let path = |method:Method, uri:&str| {
+            let req = HttpRequest { method, version: ApiVersion::V1, uri: uri.to_string(), accept: "application/json", timeout: None, headers: HeaderMap::new() };
+            fixtures.fixture_path(&req)
+        };
+        let quotes = path(Method::GET, "/markets/quotes?symbols=SPY&greeks=false");
+        assert_eq!(quotes, path(Method::GET, "/markets/quotes?greeks=false&symbols=SPY"));
+        assert_eq!(quotes, path(Method::GET, "/markets/quotes?greeks=false&symbols=%53PY"));
+        assert!(quotes.file_name().unwrap().to_str().unwrap().starts_with("GET_markets_quotes-"));
+        // Queries that sanitize to the same name still get their own files.
+        assert_ne!(path(Method::GET, "/markets/quotes?symbols=A/B"), path(Method::GET, "/markets/quotes?symbols=A_B"));
+        assert_ne!(quotes, path(Method::POST, "/markets/quotes?symbols=SPY&greeks=false"));
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[tokio::test]
+    async fn test_conditional_transport() {
+        let mock = MockTransport::new();
+        let mut resp = HttpResponse::new(StatusCode::OK, r#"{"expirations":{}}"#);
+        resp.headers.insert(ETAG, "\"v1\"".parse().unwrap());
+        mock.add_response("/markets/options/expirations", resp);
+        mock.add_response("/markets/options/expirations", HttpResponse::new(StatusCode::NOT_MODIFIED, ""));
+        let conditional = ConditionalTransport::new(mock);
+        let opts = RequestOptions::default();
+
+        for _ in 0..2 {
+            let body = tradier_request_with(&conditional, Method::GET, "/markets/options/expirations?symbol=SPY", &opts).await.unwrap();
+            assert_eq!(body, r#"{"expirations":{}}"#);
+        }
+        let reqs = conditional.inner.requests();
+        assert_eq!(reqs[0].headers.get(IF_NONE_MATCH), None);
+        assert_eq!(reqs[1].headers.get(IF_NONE_MATCH).unwrap(), "\"v1\"");
+    }
+
+    #[tokio::test]
+    async fn test_conditional_transport_eviction() {
+        let mut resp = HttpResponse::new(StatusCode::OK, "{}");
+        resp.headers.insert(ETAG, "\"v1\"".parse().unwrap());
+        let mock = MockTransport::new();
+        mock.add_response("/markets/calendar", resp);
+        let conditional = ConditionalTransport::with_capacity(mock, 2);
+        let opts = RequestOptions::default();
+        for month in ["01", "02", "01", "03"] {
+            tradier_request_with(&conditional, Method::GET, &format!("/markets/calendar?month={}", month), &opts).await.unwrap();
+        }
+        // 02 was used least recently when 03 arrived.
+        let mut keys: Vec<_> = conditional.cache.lock().unwrap().entries.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, ["/v1/markets/calendar?month=01", "/v1/markets/calendar?month=03"]);
+        assert_eq!(conditional.len(), 2);
+        conditional.clear();
+        assert!(conditional.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_response_meta() {
+        let mock = MockTransport::new();
+        let mut resp = HttpResponse::new(StatusCode::OK, "{}");
+        resp.headers.insert("X-Ratelimit-Allowed", "120".parse().unwrap());
+        resp.headers.insert("X-Ratelimit-Used", "3".parse().unwrap());
+        resp.headers.insert("X-Ratelimit-Available", "117".parse().unwrap());
+        resp.headers.insert("X-Ratelimit-Expiry", "1712000060000".parse().unwrap());
+        resp.headers.insert("X-Request-Id", "req-1".parse().unwrap());
+        mock.add_response("/markets/quotes", resp);
+
+        let (body, meta) = tradier_request_with_meta_with(&mock, Method::GET, "/markets/quotes?symbols=SPY", &RequestOptions::default()).await.unwrap();
+        assert_eq!(body, "{}");
+        assert_eq!(meta.request_id.as_deref(), Some("req-1"));
+        assert_eq!(meta.status, StatusCode::OK);
+        assert_eq!(meta.rate_limit, Some(RateLimit {
+            allowed: 120, used: 3, available: 117, expiry: DateTime::from_timestamp_millis(1712000060000).unwrap(),
+        }));
+
+        // Missing or partial headers give None.
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Ratelimit-Allowed", "120".parse().unwrap());
+        assert_eq!(ResponseMeta::from_headers(&headers), ResponseMeta::default());
+        // Values that don't fit are dropped rather than wrapped.
+        headers.insert("X-Ratelimit-Used", "4294967296".parse().unwrap());
+        headers.insert("X-Ratelimit-Available", "1".parse().unwrap());
+        headers.insert("X-Ratelimit-Expiry", "1712000060000".parse().unwrap());
+        assert_eq!(ResponseMeta::from_headers(&headers).rate_limit, None);
+    }
+}
@@ -0,0 +1,209 @@
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{Stream, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use crate::{data::MarketData, events::StreamEvent};
+
+/// An indicator updated one trade print at a time, so nothing has to be buffered and recomputed.
+pub trait Indicator {
+    type Output;
+    fn update(&mut self, timestamp:DateTime<Utc>, price:f64, size:u64) -> Self::Output;
+}
+
+/// Volume weighted average price since creation or the last reset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Vwap {
+    price_volume: f64,
+    volume: u64,
+}
+
+impl Vwap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// None until some volume has traded.
+    pub fn value(&self) -> Option<f64> {
+        (self.volume > 0).then(|| self.price_volume / self.volume as f64)
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.volume
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl Indicator for Vwap {
+    type Output = f64;
+
+    fn update(&mut self, _timestamp:DateTime<Utc>, price:f64, size:u64) -> f64 {
+        self.price_volume += price * size as f64;
+        self.volume += size;
+        self.value().unwrap_or(price)
+    }
+}
+
+/// Exponential moving average of prices with the usual smoothing of 2 / (period + 1).
+/// Seeded with the first price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ema {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ema {
+    pub fn new(period:u32) -> Self {
+        Self { alpha: 2.0 / (period.max(1) as f64 + 1.0), value: None }
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+impl Indicator for Ema {
+    type Output = f64;
+
+    fn update(&mut self, _timestamp:DateTime<Utc>, price:f64, _size:u64) -> f64 {
+        let value = match self.value {
+            Some(prev) => prev + self.alpha * (price - prev),
+            None => price,
+        };
+        self.value = Some(value);
+        value
+    }
+}
+
+/// Highest and lowest price over a trailing time window, eg. the last 5 minutes.
+/// Keeps monotonic queues so each update is amortized O(1).
+#[derive(Debug, Clone)]
+pub struct RollingHighLow {
+    window: Duration,
+    highs: VecDeque<(DateTime<Utc>, f64)>,
+    lows: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl RollingHighLow {
+    pub fn new(window:Duration) -> Self {
+        Self { window, highs: VecDeque::new(), lows: VecDeque::new() }
+    }
+
+    /// (high, low), None until the first update.
+    pub fn value(&self) -> Option<(f64, f64)> {
+        Some((self.highs.front()?.1, self.lows.front()?.1))
+    }
+}
+
+impl Indicator for RollingHighLow {
+    type Output = (f64, f64);
+
+    fn update(&mut self, timestamp:DateTime<Utc>, price:f64, _size:u64) -> (f64, f64) {
+        while self.highs.back().is_some_and(|(_, p)| *p <= price) {
+            self.highs.pop_back();
+        }
+        while self.lows.back().is_some_and(|(_, p)| *p >= price) {
+            self.lows.pop_back();
+        }
+        self.highs.push_back((timestamp, price));
+        self.lows.push_back((timestamp, price));
+        let cutoff = timestamp - self.window;
+        while self.highs.front().is_some_and(|(t, _)| *t <= cutoff) {
+            self.highs.pop_front();
+        }
+        while self.lows.front().is_some_and(|(t, _)| *t <= cutoff) {
+            self.lows.pop_front();
+        }
+        (self.highs.front().unwrap().1, self.lows.front().unwrap().1)
+    }
+}
+
+/// Symbol, price and size of a trade print: trade and tradex events, and timesales that weren't canceled.
+/// Subscribe to trades or timesales, not both, or prints are counted twice.
+pub fn trade_print(event:&StreamEvent) -> Option<(&str, f64, u64)> {
+    match event {
+        StreamEvent::Trade(t) | StreamEvent::Tradex(t) => Some((&t.symbol, t.price, t.size)),
+        StreamEvent::Timesale(t) if !t.cancel => Some((&t.symbol, t.last, t.size)),
+        _ => None,
+    }
+}
+
+/// Combinators computing indicators over a stream of typed events, eg. from [`crate::stream::stream_symbols`].
+pub trait IndicatorStreamExt: Stream<Item = MarketData<StreamEvent>> + Sized {
+    /// Pairs each event with the output of an indicator kept per symbol, created by make on the symbol's first print.
+    /// The output is None for events that aren't trade prints, see [`trade_print`].
+    fn with_indicator<I:Indicator, F:FnMut() -> I>(self, mut make:F) -> impl Stream<Item = (MarketData<StreamEvent>, Option<I::Output>)> {
+        let mut indicators = HashMap::<String, I>::new();
+        self.map(move |md| {
+            let output = trade_print(&md.data).map(|(symbol, price, size)| {
+                let indicator = match indicators.get_mut(symbol) {
+                    Some(indicator) => indicator,
+                    None => indicators.entry(symbol.to_string()).or_insert_with(&mut make),
+                };
+                indicator.update(md.timestamp, price, size)
+            });
+            (md, output)
+        })
+    }
+
+    /// Per symbol VWAP since the stream started.
+    fn with_vwap(self) -> impl Stream<Item = (MarketData<StreamEvent>, Option<f64>)> {
+        self.with_indicator(Vwap::new)
+    }
+
+    fn with_ema(self, period:u32) -> impl Stream<Item = (MarketData<StreamEvent>, Option<f64>)> {
+        self.with_indicator(move || Ema::new(period))
+    }
+
+    /// Per symbol (high, low) over the trailing window.
+    fn with_high_low(self, window:Duration) -> impl Stream<Item = (MarketData<StreamEvent>, Option<(f64, f64)>)> {
+        self.with_indicator(move || RollingHighLow::new(window))
+    }
+}
+
+impl<S:Stream<Item = MarketData<StreamEvent>>> IndicatorStreamExt for S {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::parse_event;
+
+    fn at(sec:i64) -> DateTime<Utc> {
+        chrono::NaiveDateTime::parse_from_str("2024-07-01 14:00:00", "%Y-%m-%d %H:%M:%S").unwrap().and_utc() + Duration::seconds(sec)
+    }
+
+    fn event(sec:i64, json:&str) -> MarketData<StreamEvent> {
+        MarketData { timestamp: at(sec), data: parse_event(json).unwrap() }
+    }
+
+    fn trade(sec:i64, symbol:&str, price:f64, size:u64) -> MarketData<StreamEvent> {
+        event(sec, &format!(r#"{{"type":"trade","symbol":"{}","price":"{}","size":"{}","cvol":"0","date":"0","last":"{}"}}"#, symbol, price, size, price))
+    }
+
+    #[test]
+    fn test_indicators() {
+        let mut ema = Ema::new(3);
+        assert_eq!(ema.update(at(0), 10.0, 0), 10.0);
+        assert_eq!(ema.update(at(1), 12.0, 0), 11.0);
+
+        let mut hl = RollingHighLow::new(Duration::seconds(10));
+        assert_eq!(hl.update(at(0), 10.0, 0), (10.0, 10.0));
+        assert_eq!(hl.update(at(5), 12.0, 0), (12.0, 10.0));
+        assert_eq!(hl.update(at(9), 11.0, 0), (12.0, 10.0));
+        assert_eq!(hl.update(at(12), 11.5, 0), (12.0, 11.0));
+        assert_eq!(hl.update(at(16), 11.2, 0), (11.5, 11.0));
+    }
+
+    #[tokio::test]
+    async fn test_with_vwap() {
+        let events = futures_util::stream::iter(vec![
+            trade(0, "SPY", 10.0, 100),
+            event(1, r#"{"type":"quote","symbol":"SPY","bid":"9.9","ask":"10.1","bidsz":"1","asksz":"1","biddate":"0","askdate":"0"}"#),
+            trade(2, "QQQ", 50.0, 10),
+            trade(3, "SPY", 11.0, 300),
+        ]);
+        let out = events.with_vwap().map(|(_, vwap)| vwap).collect::<Vec<_>>().await;
+        assert_eq!(out, [Some(10.0), None, Some(50.0), Some(10.75)]);
+    }
+}
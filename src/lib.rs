@@ -1,3 +1,38 @@
 // #![feature(asm)]
 
-pub mod data;
\ No newline at end of file
+pub mod auth;
+pub mod data;
+pub mod error;
+pub mod http;
+pub mod markets;
+pub mod history;
+pub mod net;
+pub mod options;
+pub mod margin;
+pub mod recorder;
+pub mod throttle;
+pub mod events;
+pub mod state;
+pub mod stream;
+pub mod polling;
+pub mod market_hours;
+pub mod watchdog;
+pub mod sequence;
+pub mod symbol;
+pub mod bars;
+pub mod quote_tracker;
+pub mod book;
+pub mod strategies;
+pub mod analytics;
+pub mod pnl;
+pub mod volume_profile;
+pub mod indicators;
+pub mod scheduler;
+pub mod serde_helpers;
+pub mod prelude;
+#[cfg(all(feature = "stream", any(test, feature = "testing")))]
+pub mod testing;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "blocking")]
+pub mod blocking;
@@ -0,0 +1,243 @@
+use crate::options::{OptionRight, OptionSpec};
+
+/// Contract multiplier for standard equity and index options.
+pub const CONTRACT_MULTIPLIER: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountType {
+    Cash,
+    Margin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegSide {
+    Long,
+    Short,
+}
+
+/// One leg of an option strategy. Premium is per share, so 1.25 means $125 per contract.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrategyLeg {
+    pub spec: OptionSpec,
+    pub side: LegSide,
+    pub quantity: u32,
+    pub premium: f64,
+}
+
+impl StrategyLeg {
+    pub fn new(spec:OptionSpec, side:LegSide, quantity:u32, premium:f64) -> Self {
+        Self { spec, side, quantity, premium }
+    }
+
+    /// Premium for the whole leg, positive when received and negative when paid.
+    fn signed_premium(&self) -> f64 {
+        let total = self.premium * CONTRACT_MULTIPLIER * self.quantity as f64;
+        match self.side {
+            LegSide::Long => -total,
+            LegSide::Short => total,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarginEstimate {
+    /// Reg-T initial requirement. For short options this includes the premium received, as the rules specify.
+    pub requirement: f64,
+    /// Net premium for all legs, positive for a credit and negative for a debit.
+    pub net_premium: f64,
+}
+
+impl MarginEstimate {
+    /// The amount of buying power the position ties up once the premium is settled.
+    pub fn buying_power_effect(&self) -> f64 {
+        self.requirement - self.net_premium
+    }
+}
+
+/// Estimates Reg-T style margin for common option structures: single long or short options,
+/// vertical spreads, long and short straddles/strangles, and iron condors/butterflies.
+/// This is an offline estimate for position sizing; the broker's own calculation (eg. from an order preview)
+/// is authoritative and may differ for house rules.
+/// Returns None if the legs aren't a supported structure, or the structure isn't allowed in the account type
+/// (eg. naked calls in a cash account).
+pub fn estimate_margin(legs:&[StrategyLeg], underlying_price:f64, account_type:AccountType) -> Option<MarginEstimate> {
+    let first = legs.first()?;
+    let quantity = first.quantity;
+    if quantity == 0 || legs.iter().any(|leg| leg.quantity != quantity || leg.spec.underlying != first.spec.underlying) {
+        return None;
+    }
+    let net_premium = legs.iter().map(StrategyLeg::signed_premium).sum();
+    let per_share = match legs {
+        [leg] => single_requirement(leg, underlying_price, account_type)?,
+        [a, b] => pair_requirement(a, b, underlying_price, account_type)?,
+        [_, _, _, _] => four_leg_requirement(legs)?,
+        _ => return None,
+    };
+    Some(MarginEstimate { requirement: per_share * CONTRACT_MULTIPLIER * quantity as f64, net_premium })
+}
+
+fn single_requirement(leg:&StrategyLeg, underlying_price:f64, account_type:AccountType) -> Option<f64> {
+    match (leg.side, account_type) {
+        // Long options are paid in full, nothing beyond the premium is required.
+        (LegSide::Long, _) => Some(0.0),
+        (LegSide::Short, AccountType::Margin) => Some(naked_requirement(leg, underlying_price)),
+        // Cash accounts can only write cash secured puts.
+        (LegSide::Short, AccountType::Cash) => match leg.spec.right {
+            OptionRight::Put => Some(leg.spec.strike),
+            OptionRight::Call => None,
+        },
+    }
+}
+
+fn pair_requirement(a:&StrategyLeg, b:&StrategyLeg, underlying_price:f64, account_type:AccountType) -> Option<f64> {
+    // Two long options, eg. a long straddle or strangle, are each paid in full like a single long option.
+    if a.side == LegSide::Long && b.side == LegSide::Long {
+        return Some(0.0);
+    }
+    if a.spec.expiration != b.spec.expiration {
+        return None;
+    }
+    if a.spec.right == b.spec.right {
+        let (long, short) = match (a.side, b.side) {
+            (LegSide::Long, LegSide::Short) => (a, b),
+            (LegSide::Short, LegSide::Long) => (b, a),
+            _ => return None,
+        };
+        return Some(vertical_requirement(long, short));
+    }
+    // Call and put: only short straddles/strangles are supported.
+    if a.side != LegSide::Short || b.side != LegSide::Short || account_type == AccountType::Cash {
+        return None;
+    }
+    // Margin for the side with the greater requirement plus the premium of the other side.
+    let (req_a, req_b) = (naked_requirement(a, underlying_price), naked_requirement(b, underlying_price));
+    Some(if req_a >= req_b { req_a + b.premium } else { req_b + a.premium })
+}
+
+/// Two vertical spreads, one of calls and one of puts, with the same expiration.
+fn four_leg_requirement(legs:&[StrategyLeg]) -> Option<f64> {
+    let expiration = legs[0].spec.expiration;
+    if legs.iter().any(|leg| leg.spec.expiration != expiration) {
+        return None;
+    }
+    let side_legs = |right:OptionRight| -> Option<(&StrategyLeg, &StrategyLeg)> {
+        let mut long = None;
+        let mut short = None;
+        for leg in legs.iter().filter(|leg| leg.spec.right == right) {
+            let slot = match leg.side { LegSide::Long => &mut long, LegSide::Short => &mut short };
+            if slot.replace(leg).is_some() {
+                return None;
+            }
+        }
+        Some((long?, short?))
+    };
+    let (call_long, call_short) = side_legs(OptionRight::Call)?;
+    let (put_long, put_short) = side_legs(OptionRight::Put)?;
+    // Both sides can't finish in the money, so only the larger requirement applies.
+    Some(vertical_requirement(call_long, call_short).max(vertical_requirement(put_long, put_short)))
+}
+
+/// Credit spreads require the max loss (strike width). Debit spreads require only the premium paid.
+fn vertical_requirement(long:&StrategyLeg, short:&StrategyLeg) -> f64 {
+    let width = match short.spec.right {
+        OptionRight::Call => long.spec.strike - short.spec.strike,
+        OptionRight::Put => short.spec.strike - long.spec.strike,
+    };
+    width.max(0.0)
+}
+
+/// Per share Reg-T requirement for an uncovered short option:
+/// premium + 20% of underlying - out of the money amount, with a minimum of premium + 10% of underlying (calls)
+/// or strike (puts).
+fn naked_requirement(leg:&StrategyLeg, underlying_price:f64) -> f64 {
+    let strike = leg.spec.strike;
+    let (otm, minimum) = match leg.spec.right {
+        OptionRight::Call => ((strike - underlying_price).max(0.0), 0.1 * underlying_price),
+        OptionRight::Put => ((underlying_price - strike).max(0.0), 0.1 * strike),
+    };
+    leg.premium + (0.2 * underlying_price - otm).max(minimum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn leg(right:OptionRight, strike:f64, side:LegSide, premium:f64) -> StrategyLeg {
+        let exp = NaiveDate::from_ymd_opt(2024, 4, 19).unwrap();
+        StrategyLeg::new(OptionSpec::new("XYZ", exp, right, strike), side, 1, premium)
+    }
+
+    fn assert_close(a:f64, b:f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_naked() {
+        let put = leg(OptionRight::Put, 95.0, LegSide::Short, 2.0);
+        let est = estimate_margin(std::slice::from_ref(&put), 100.0, AccountType::Margin).unwrap();
+        assert_close(est.requirement, 1700.0);
+        assert_close(est.buying_power_effect(), 1500.0);
+
+        // Far out of the money hits the 10% minimum.
+        let far_put = leg(OptionRight::Put, 60.0, LegSide::Short, 0.1);
+        assert_close(estimate_margin(&[far_put], 100.0, AccountType::Margin).unwrap().requirement, 610.0);
+
+        let call = leg(OptionRight::Call, 105.0, LegSide::Short, 1.5);
+        assert_close(estimate_margin(std::slice::from_ref(&call), 100.0, AccountType::Margin).unwrap().requirement, 1650.0);
+
+        let cash_put = estimate_margin(&[put], 100.0, AccountType::Cash).unwrap();
+        assert_close(cash_put.buying_power_effect(), 9300.0);
+        assert_eq!(estimate_margin(&[call], 100.0, AccountType::Cash), None);
+    }
+
+    #[test]
+    fn test_spreads() {
+        let credit = [leg(OptionRight::Put, 95.0, LegSide::Short, 2.0), leg(OptionRight::Put, 90.0, LegSide::Long, 1.0)];
+        let est = estimate_margin(&credit, 100.0, AccountType::Margin).unwrap();
+        assert_close(est.requirement, 500.0);
+        assert_close(est.net_premium, 100.0);
+        assert_close(est.buying_power_effect(), 400.0);
+        assert_eq!(estimate_margin(&credit, 100.0, AccountType::Cash), Some(est));
+
+        let debit = [leg(OptionRight::Call, 100.0, LegSide::Long, 4.0), leg(OptionRight::Call, 105.0, LegSide::Short, 1.5)];
+        let est = estimate_margin(&debit, 100.0, AccountType::Margin).unwrap();
+        assert_close(est.requirement, 0.0);
+        assert_close(est.buying_power_effect(), 250.0);
+    }
+
+    #[test]
+    fn test_straddle_and_condor() {
+        let straddle = [leg(OptionRight::Call, 100.0, LegSide::Short, 3.0), leg(OptionRight::Put, 100.0, LegSide::Short, 2.8)];
+        let est = estimate_margin(&straddle, 100.0, AccountType::Margin).unwrap();
+        assert_close(est.requirement, 2580.0);
+        assert_close(est.buying_power_effect(), 2000.0);
+        assert_eq!(estimate_margin(&straddle, 100.0, AccountType::Cash), None);
+
+        // A long strangle needs only the premium paid, in either account type.
+        let strangle = [leg(OptionRight::Call, 105.0, LegSide::Long, 1.5), leg(OptionRight::Put, 95.0, LegSide::Long, 1.0)];
+        for account_type in [AccountType::Margin, AccountType::Cash] {
+            let est = estimate_margin(&strangle, 100.0, account_type).unwrap();
+            assert_close(est.requirement, 0.0);
+            assert_close(est.buying_power_effect(), 250.0);
+        }
+
+        let condor = [
+            leg(OptionRight::Put, 90.0, LegSide::Long, 0.5),
+            leg(OptionRight::Put, 95.0, LegSide::Short, 1.2),
+            leg(OptionRight::Call, 105.0, LegSide::Short, 1.1),
+            leg(OptionRight::Call, 115.0, LegSide::Long, 0.3),
+        ];
+        let est = estimate_margin(&condor, 100.0, AccountType::Margin).unwrap();
+        assert_close(est.requirement, 1000.0);
+        assert_close(est.net_premium, 150.0);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut ratio = [leg(OptionRight::Put, 95.0, LegSide::Short, 2.0), leg(OptionRight::Put, 90.0, LegSide::Long, 1.0)];
+        ratio[0].quantity = 2;
+        assert_eq!(estimate_margin(&ratio, 100.0, AccountType::Margin), None);
+        assert_eq!(estimate_margin(&[], 100.0, AccountType::Margin), None);
+    }
+}
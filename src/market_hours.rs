@@ -0,0 +1,66 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+/// Converts a UTC time to US/Eastern, applying the US daylight saving rules in effect since 2007:
+/// from 2am on the second Sunday in March to 2am on the first Sunday in November.
+pub fn to_eastern(utc:NaiveDateTime) -> NaiveDateTime {
+    utc + Duration::hours(eastern_offset_hours(utc))
+}
+
+/// Converts a US/Eastern time to UTC. Ambiguous times in the hour when clocks go back are taken as daylight time.
+pub fn from_eastern(eastern:NaiveDateTime) -> NaiveDateTime {
+    let dst = eastern - Duration::hours(-4);
+    if eastern_offset_hours(dst) == -4 { dst } else { eastern - Duration::hours(-5) }
+}
+
+/// -4 during daylight saving time, otherwise -5.
+pub fn eastern_offset_hours(utc:NaiveDateTime) -> i64 {
+    let year = utc.year();
+    // 2am EST = 7:00 UTC, 2am EDT = 6:00 UTC.
+    let dst_start = nth_sunday(year, 3, 2).and_hms_opt(7, 0, 0).unwrap();
+    let dst_end = nth_sunday(year, 11, 1).and_hms_opt(6, 0, 0).unwrap();
+    if utc >= dst_start && utc < dst_end { -4 } else { -5 }
+}
+
+/// True during regular US equity market hours, 9:30 to 16:00 Eastern on weekdays.
+/// Holidays and early closes aren't known here; use the market calendar endpoints for those.
+pub fn is_regular_hours(utc:NaiveDateTime) -> bool {
+    let eastern = to_eastern(utc);
+    let open = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+    let close = NaiveTime::from_hms_opt(16, 0, 0).unwrap();
+    !matches!(eastern.weekday(), Weekday::Sat | Weekday::Sun) && eastern.time() >= open && eastern.time() < close
+}
+
+fn nth_sunday(year:i32, month:u32, n:u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, n).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s:&str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_eastern_offset() {
+        // 2024 DST: March 10 to November 3.
+        assert_eq!(eastern_offset_hours(utc("2024-03-10 06:59")), -5);
+        assert_eq!(eastern_offset_hours(utc("2024-03-10 07:00")), -4);
+        assert_eq!(eastern_offset_hours(utc("2024-11-03 05:59")), -4);
+        assert_eq!(eastern_offset_hours(utc("2024-11-03 06:00")), -5);
+        assert_eq!(to_eastern(utc("2024-07-01 13:30")), utc("2024-07-01 09:30"));
+        assert_eq!(from_eastern(utc("2024-07-01 09:30")), utc("2024-07-01 13:30"));
+        assert_eq!(from_eastern(utc("2024-01-02 09:30")), utc("2024-01-02 14:30"));
+    }
+
+    #[test]
+    fn test_regular_hours() {
+        assert!(is_regular_hours(utc("2024-07-01 13:30")));
+        assert!(!is_regular_hours(utc("2024-07-01 13:29")));
+        assert!(!is_regular_hours(utc("2024-07-01 20:00")));
+        assert!(is_regular_hours(utc("2024-01-02 20:59")));
+        // Saturday
+        assert!(!is_regular_hours(utc("2024-07-06 15:00")));
+    }
+}
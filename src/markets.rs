@@ -0,0 +1,512 @@
+//! Market data REST calls. Like [`crate::http::tradier_get`] most return the response body as sent, [`get_quote`] parses it.
+//! The `_with` versions send through the given transport instead of the global one.
+
+use chrono::{Days, Months, NaiveDate};
+use futures_util::{future::try_join_all, stream, StreamExt, TryStreamExt};
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use crate::{error::Error, http::{query_uri, tradier_request, tradier_request_with, HttpTransport, RequestOptions}, symbol::Symbol};
+
+/// Bar size for [`get_history`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryInterval {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl HistoryInterval {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HistoryInterval::Daily => "daily",
+            HistoryInterval::Weekly => "weekly",
+            HistoryInterval::Monthly => "monthly",
+        }
+    }
+}
+
+/// Which bars [`get_history`] includes. Tradier defaults to all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionFilter {
+    /// Bars from the regular session and extended hours.
+    All,
+    /// Only bars from the regular session.
+    Open,
+}
+
+impl SessionFilter {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SessionFilter::All => "all",
+            SessionFilter::Open => "open",
+        }
+    }
+}
+
+/// Options for [`get_history`]. The default sends no session_filter and returns the prices as sent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryOptions {
+    pub session_filter: Option<SessionFilter>,
+    /// Adds adj_close to every bar: close back-adjusted for these cash dividends, given as ex-date and amount per
+    /// share. Tradier's prices are split adjusted but not dividend adjusted, so with none adj_close equals close.
+    pub adj_close: Option<Vec<(NaiveDate, f64)>>,
+}
+
+/// The type field of a [`Quote`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteType {
+    Stock,
+    Etf,
+    Index,
+    Option,
+    MutualFund,
+    #[serde(other)]
+    Other,
+}
+
+/// One quote from `/markets/quotes`, see [`parse_quotes`]. Index quotes such as SPX or VIX have no exchange and
+/// usually no bid or ask, so those fields are optional and only present when Tradier sent a value.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Quote {
+    pub symbol: String,
+    #[serde(rename = "type")]
+    pub kind: QuoteType,
+    pub description: Option<String>,
+    #[serde(rename = "exch", default, deserialize_with = "non_empty")]
+    pub exchange: Option<String>,
+    pub last: Option<f64>,
+    pub bid: Option<f64>,
+    pub ask: Option<f64>,
+    #[serde(rename = "bidexch", default, deserialize_with = "non_empty")]
+    pub bid_exchange: Option<String>,
+    #[serde(rename = "askexch", default, deserialize_with = "non_empty")]
+    pub ask_exchange: Option<String>,
+    /// Fields not modeled above, eg. volume, greeks or the option details.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Quote {
+    pub fn is_index(&self) -> bool {
+        self.kind == QuoteType::Index
+    }
+}
+
+/// Indexes come with an empty string for exchanges as often as null.
+fn non_empty<'de, D:serde::Deserializer<'de>>(d:D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<String>::deserialize(d)?.filter(|s| !s.is_empty()))
+}
+
+/// `/markets/quotes` for the symbols, encoded for the query string. With greeks, option quotes include a greeks
+/// object with the delta, gamma, theta, vega, rho and implied volatilities.
+pub fn quotes_uri(symbols:&[&str], greeks:bool) -> String {
+    let symbols = symbols.join(",");
+    if greeks {
+        query_uri("/markets/quotes", &[("symbols", &symbols), ("greeks", "true")])
+    } else {
+        query_uri("/markets/quotes", &[("symbols", &symbols)])
+    }
+}
+
+/// `/markets/history` for the symbol between start and end inclusive.
+pub fn history_uri(symbol:&str, interval:HistoryInterval, start:NaiveDate, end:NaiveDate, session_filter:Option<SessionFilter>) -> String {
+    let (start, end) = (start.format("%Y-%m-%d").to_string(), end.format("%Y-%m-%d").to_string());
+    let mut params = vec![("symbol", symbol), ("interval", interval.as_str()), ("start", &start), ("end", &end)];
+    if let Some(filter) = session_filter {
+        params.push(("session_filter", filter.as_str()));
+    }
+    query_uri("/markets/history", &params)
+}
+
+/// Current quotes. quote in the response is an object for a single symbol and a list for several.
+pub async fn get_quotes(symbols:&[&str], greeks:bool) -> Result<String, Error> {
+    tradier_request(Method::GET, &quotes_uri(symbols, greeks), &RequestOptions::default()).await
+}
+
+pub async fn get_quotes_with(transport:&dyn HttpTransport, symbols:&[&str], greeks:bool) -> Result<String, Error> {
+    tradier_request_with(transport, Method::GET, &quotes_uri(symbols, greeks), &RequestOptions::default()).await
+}
+
+/// Quotes for any number of symbols, sent as requests of at most chunk_size symbols with up to parallelism
+/// of them in flight at once, so long lists stay under Tradier's URL length limit.
+/// The result has the shape of a [`get_quotes`] response with quote always a list, in the order of symbols.
+pub async fn get_quotes_batched(symbols:&[&str], greeks:bool, chunk_size:usize, parallelism:usize) -> Result<String, Error> {
+    quotes_batched(None, symbols, greeks, chunk_size, parallelism).await
+}
+
+pub async fn get_quotes_batched_with(transport:&dyn HttpTransport, symbols:&[&str], greeks:bool, chunk_size:usize, parallelism:usize) -> Result<String, Error> {
+    quotes_batched(Some(transport), symbols, greeks, chunk_size, parallelism).await
+}
+
+async fn quotes_batched(transport:Option<&dyn HttpTransport>, symbols:&[&str], greeks:bool, chunk_size:usize, parallelism:usize) -> Result<String, Error> {
+    if chunk_size == 0 {
+        return Err(Error::InvalidRequest("Chunk size must be at least 1".to_string()));
+    }
+    let bodies: Vec<String> = stream::iter(symbols.chunks(chunk_size)).map(|chunk| async move {
+        match transport {
+            Some(transport) => get_quotes_with(transport, chunk, greeks).await,
+            None => get_quotes(chunk, greeks).await,
+        }
+    }).buffered(parallelism.max(1)).try_collect().await?;
+    merge_quotes(&bodies)
+}
+
+/// A single quote. The symbol is normalized with [`Symbol::parse`] first, so indexes can be given as `SPX`, `$SPX`
+/// or `^SPX`. Fails with [`Error::InvalidSymbol`] if the symbol is malformed or Tradier doesn't know it.
+pub async fn get_quote(symbol:&str) -> Result<Quote, Error> {
+    let symbol = Symbol::parse(symbol)?;
+    single_quote(&get_quotes(&[symbol.as_str()], false).await?, &symbol)
+}
+
+pub async fn get_quote_with(transport:&dyn HttpTransport, symbol:&str) -> Result<Quote, Error> {
+    let symbol = Symbol::parse(symbol)?;
+    single_quote(&get_quotes_with(transport, &[symbol.as_str()], false).await?, &symbol)
+}
+
+fn single_quote(body:&str, symbol:&Symbol) -> Result<Quote, Error> {
+    parse_quotes(body)?.into_iter().next().ok_or_else(|| Error::InvalidSymbol(format!("{:?}: no quote returned", symbol.as_str())))
+}
+
+/// The quotes in a `/markets/quotes` response, empty if none matched.
+pub fn parse_quotes(body:&str) -> Result<Vec<Quote>, Error> {
+    let data: Value = serde_json::from_str(body).map_err(|e| Error::Parse(format!("quotes: {}", e)))?;
+    let mut quotes = Vec::new();
+    extend_one_or_many(&mut quotes, &data["quotes"]["quote"]);
+    quotes.into_iter().map(|q| serde_json::from_value(q).map_err(|e| Error::Parse(format!("quote: {}", e)))).collect()
+}
+
+/// Concatenates the quote and unmatched_symbols lists of several quotes responses, in order.
+fn merge_quotes(bodies:&[String]) -> Result<String, Error> {
+    let (mut quotes, mut unmatched) = (Vec::new(), Vec::new());
+    for body in bodies {
+        let data: Value = serde_json::from_str(body).map_err(|e| Error::Parse(format!("quotes: {}", e)))?;
+        extend_one_or_many(&mut quotes, &data["quotes"]["quote"]);
+        extend_one_or_many(&mut unmatched, &data["quotes"]["unmatched_symbols"]["symbol"]);
+    }
+    let mut merged = json!({"quotes": {"quote": quotes}});
+    if !unmatched.is_empty() {
+        merged["quotes"]["unmatched_symbols"] = json!({"symbol": unmatched});
+    }
+    Ok(merged.to_string())
+}
+
+/// Tradier sends a single element instead of a list of one, and null or nothing for none.
+pub(crate) fn extend_one_or_many(list:&mut Vec<Value>, value:&Value) {
+    match value {
+        Value::Array(values) => list.extend(values.iter().cloned()),
+        Value::Null => {},
+        value => list.push(value.clone()),
+    }
+}
+
+/// Fails with [`Error::InvalidRequest`] if the range ends before it starts, which Tradier would answer with an
+/// empty history rather than an error.
+pub(crate) fn check_range(start:NaiveDate, end:NaiveDate) -> Result<(), Error> {
+    if start > end {
+        return Err(Error::InvalidRequest("Start date must be before or equal to end date".to_string()));
+    }
+    Ok(())
+}
+
+/// Historical OHLCV bars. history is null in the response if there are none in the range.
+/// Daily ranges longer than a year are fetched a year at a time, in order, and the days joined into one response.
+/// Weekly and monthly ranges are sent whole, they have few bars and splitting them would cut bars in two.
+pub async fn get_history(symbol:&str, interval:HistoryInterval, start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<String, Error> {
+    history(None, symbol, interval, start, end, options).await
+}
+
+pub async fn get_history_with(transport:&dyn HttpTransport, symbol:&str, interval:HistoryInterval, start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<String, Error> {
+    history(Some(transport), symbol, interval, start, end, options).await
+}
+
+async fn history(transport:Option<&dyn HttpTransport>, symbol:&str, interval:HistoryInterval, start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<String, Error> {
+    check_range(start, end)?;
+    let ranges = match interval {
+        HistoryInterval::Daily => year_ranges(start, end),
+        HistoryInterval::Weekly | HistoryInterval::Monthly => vec![(start, end)],
+    };
+    let mut bodies = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        let uri = history_uri(symbol, interval, start, end, options.session_filter);
+        bodies.push(match transport {
+            Some(transport) => tradier_request_with(transport, Method::GET, &uri, &RequestOptions::default()).await?,
+            None => tradier_request(Method::GET, &uri, &RequestOptions::default()).await?,
+        });
+    }
+    let body = if bodies.len() == 1 { bodies.pop().unwrap() } else { merge_history(&bodies)? };
+    match &options.adj_close {
+        Some(dividends) => add_adj_close(&body, dividends),
+        None => Ok(body),
+    }
+}
+
+/// Sets adj_close on each bar to close times the product of (1 - dividend / previous close) for every dividend
+/// going ex after it, the usual back adjustment. day becomes a list if it was a single bar.
+fn add_adj_close(body:&str, dividends:&[(NaiveDate, f64)]) -> Result<String, Error> {
+    let mut data: Value = serde_json::from_str(body).map_err(|e| Error::Parse(format!("history: {}", e)))?;
+    let mut days = Vec::new();
+    extend_one_or_many(&mut days, &data["history"]["day"]);
+    if days.is_empty() {
+        return Ok(body.to_string());
+    }
+    let dates: Vec<Option<NaiveDate>> = days.iter().map(|day| day["date"].as_str().and_then(|d| d.parse().ok())).collect();
+    let mut factors = vec![1.0; days.len()];
+    for &(ex_date, amount) in dividends {
+        let ex = dates.iter().position(|date| date.is_some_and(|date| date >= ex_date)).unwrap_or(days.len());
+        let Some(prev_close) = ex.checked_sub(1).and_then(|i| days[i]["close"].as_f64()).filter(|c| *c > 0.0) else { continue };
+        for factor in &mut factors[..ex] {
+            *factor *= 1.0 - amount / prev_close;
+        }
+    }
+    for (day, factor) in days.iter_mut().zip(factors) {
+        if let Some(close) = day["close"].as_f64() {
+            day["adj_close"] = json!(close * factor);
+        }
+    }
+    data["history"]["day"] = Value::Array(days);
+    Ok(data.to_string())
+}
+
+/// History for the same symbol and range at several intervals, fetched concurrently and returned in the order of
+/// intervals. If daily is among them, every weekly and monthly bar is checked to span the highs and lows of the
+/// daily bars in its period, failing with [`Error::Parse`] if the series don't reconcile.
+pub async fn get_history_multi_interval(symbol:&str, intervals:&[HistoryInterval], start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<Vec<String>, Error> {
+    history_multi_interval(None, symbol, intervals, start, end, options).await
+}
+
+pub async fn get_history_multi_interval_with(transport:&dyn HttpTransport, symbol:&str, intervals:&[HistoryInterval], start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<Vec<String>, Error> {
+    history_multi_interval(Some(transport), symbol, intervals, start, end, options).await
+}
+
+async fn history_multi_interval(transport:Option<&dyn HttpTransport>, symbol:&str, intervals:&[HistoryInterval], start:NaiveDate, end:NaiveDate, options:&HistoryOptions) -> Result<Vec<String>, Error> {
+    let bodies = try_join_all(intervals.iter().map(|interval| history(transport, symbol, *interval, start, end, options))).await?;
+    if let Some(daily) = intervals.iter().position(|interval| *interval == HistoryInterval::Daily) {
+        let daily = history_bars(&bodies[daily])?;
+        for (interval, body) in intervals.iter().zip(&bodies).filter(|(interval, _)| **interval != HistoryInterval::Daily) {
+            check_reconciles(*interval, &history_bars(body)?, &daily)?;
+        }
+    }
+    Ok(bodies)
+}
+
+/// Date, high and low of each bar in a history response.
+fn history_bars(body:&str) -> Result<Vec<(NaiveDate, f64, f64)>, Error> {
+    let data: Value = serde_json::from_str(body).map_err(|e| Error::Parse(format!("history: {}", e)))?;
+    let mut days = Vec::new();
+    extend_one_or_many(&mut days, &data["history"]["day"]);
+    days.iter().map(|day| {
+        let date = day["date"].as_str().and_then(|d| d.parse().ok());
+        match (date, day["high"].as_f64(), day["low"].as_f64()) {
+            (Some(date), Some(high), Some(low)) => Ok((date, high, low)),
+            _ => Err(Error::Parse(format!("history bar missing date, high or low: {}", day))),
+        }
+    }).collect()
+}
+
+/// Each bar covers the days from its date up to the next bar's.
+fn check_reconciles(interval:HistoryInterval, bars:&[(NaiveDate, f64, f64)], daily:&[(NaiveDate, f64, f64)]) -> Result<(), Error> {
+    const EPSILON: f64 = 1e-6;
+    for (i, &(date, high, low)) in bars.iter().enumerate() {
+        let until = bars.get(i + 1).map_or(NaiveDate::MAX, |bar| bar.0);
+        let mut inside = daily.iter().filter(|day| day.0 >= date && day.0 < until);
+        if let Some(day) = inside.find(|day| day.1 > high + EPSILON || day.2 < low - EPSILON) {
+            return Err(Error::Parse(format!("{} bar of {} doesn't span the daily bar of {}", interval.as_str(), date, day.0)));
+        }
+    }
+    Ok(())
+}
+
+/// Splits start to end inclusive into consecutive ranges of at most a year.
+pub(crate) fn year_ranges(start:NaiveDate, end:NaiveDate) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut ranges = Vec::new();
+    let mut from = start;
+    loop {
+        let next = from.checked_add_months(Months::new(12)).unwrap_or(NaiveDate::MAX);
+        if next > end {
+            ranges.push((from, end));
+            return ranges;
+        }
+        ranges.push((from, next - Days::new(1)));
+        from = next;
+    }
+}
+
+/// Concatenates the days of several history responses in order, dropping a repeated date at a boundary.
+fn merge_history(bodies:&[String]) -> Result<String, Error> {
+    let mut days: Vec<Value> = Vec::new();
+    for body in bodies {
+        let data: Value = serde_json::from_str(body).map_err(|e| Error::Parse(format!("history: {}", e)))?;
+        let mut chunk = Vec::new();
+        extend_one_or_many(&mut chunk, &data["history"]["day"]);
+        let last = days.last().map(|day| day["date"].clone());
+        days.extend(chunk.into_iter().skip_while(|day| last.as_ref().is_some_and(|last| day["date"].as_str() <= last.as_str())));
+    }
+    Ok(if days.is_empty() { json!({"history": null}) } else { json!({"history": {"day": days}}) }.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{HttpRequest, HttpResponse, MockTransport};
+    use futures_util::future::BoxFuture;
+    use reqwest::StatusCode;
+    use std::{sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+
+    #[tokio::test]
+    async fn test_markets() {
+        let transport = MockTransport::new()
+            .with_response("/markets/quotes", StatusCode::OK, r#"{"quotes":{"quote":{"symbol":"SPY"}}}"#)
+            .with_response("/markets/history", StatusCode::OK, r#"{"history":null}"#);
+        assert_eq!(get_quotes_with(&transport, &["SPY", "BRK/B"], false).await.unwrap(), r#"{"quotes":{"quote":{"symbol":"SPY"}}}"#);
+        let (start, end) = (NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(), NaiveDate::from_ymd_opt(2024, 2, 1).unwrap());
+        assert_eq!(get_history_with(&transport, "SPY", HistoryInterval::Weekly, start, end, &HistoryOptions::default()).await.unwrap(), r#"{"history":null}"#);
+        get_quotes_with(&transport, &["SPY240719C00500000"], true).await.unwrap();
+        let uris: Vec<_> = transport.requests().into_iter().map(|r| r.uri).collect();
+        assert_eq!(uris, ["/markets/quotes?symbols=SPY%2CBRK%2FB", "/markets/history?symbol=SPY&interval=weekly&start=2024-01-02&end=2024-02-01",
+            "/markets/quotes?symbols=SPY240719C00500000&greeks=true"]);
+    }
+
+    /// Answers quotes requests with the requested symbols, later chunks faster so they complete out of order.
+    #[derive(Default)]
+    struct EchoQuotes {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl HttpTransport for EchoQuotes {
+        fn send<'a>(&'a self, req:&'a HttpRequest) -> BoxFuture<'a, Result<HttpResponse, Error>> {
+            Box::pin(async move {
+                let n = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(n, Ordering::SeqCst);
+                let symbols: Vec<_> = req.uri.split_once("symbols=").unwrap().1.split('&').next().unwrap().split("%2C").map(str::to_string).collect();
+                tokio::time::sleep(Duration::from_millis(if symbols[0] == "A" { 30 } else { 5 })).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                let body = match symbols.as_slice() {
+                    [s] if s == "E" => json!({"quotes": {"quote": {"symbol": s}, "unmatched_symbols": {"symbol": "X"}}}),
+                    _ => json!({"quotes": {"quote": symbols.iter().map(|s| json!({"symbol": s})).collect::<Vec<_>>()}}),
+                };
+                Ok(HttpResponse::new(StatusCode::OK, &body.to_string()))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quotes_batched() {
+        let transport = EchoQuotes::default();
+        let body = get_quotes_batched_with(&transport, &["A", "B", "C", "D", "E"], false, 2, 2).await.unwrap();
+        let data: Value = serde_json::from_str(&body).unwrap();
+        let symbols: Vec<_> = data["quotes"]["quote"].as_array().unwrap().iter().map(|q| q["symbol"].as_str().unwrap()).collect();
+        assert_eq!(symbols, ["A", "B", "C", "D", "E"]);
+        assert_eq!(data["quotes"]["unmatched_symbols"]["symbol"], json!(["X"]));
+        assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 2);
+
+        let failing = MockTransport::new().with_response("/markets/quotes", StatusCode::BAD_REQUEST, "bad");
+        assert!(matches!(get_quotes_batched_with(&failing, &["A", "B", "C"], false, 2, 2).await, Err(Error::Status { .. })));
+        assert!(matches!(get_quotes_batched_with(&failing, &["A"], false, 0, 2).await, Err(Error::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_history_chunks() {
+        let transport = MockTransport::new()
+            .with_response("/markets/history", StatusCode::OK, r#"{"history":{"day":[{"date":"2022-01-03","close":1.0},{"date":"2022-12-30","close":2.0}]}}"#)
+            .with_response("/markets/history", StatusCode::OK, r#"{"history":null}"#)
+            .with_response("/markets/history", StatusCode::OK, r#"{"history":{"day":[{"date":"2022-12-30","close":2.0},{"date":"2024-01-02","close":3.0}]}}"#)
+            .with_response("/markets/history", StatusCode::OK, r#"{"history":{"day":{"date":"2024-03-01","close":4.0}}}"#);
+        let (start, end) = (NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+        let body = get_history_with(&transport, "SPY", HistoryInterval::Daily, start, end, &HistoryOptions::default()).await.unwrap();
+        let data: Value = serde_json::from_str(&body).unwrap();
+        let closes: Vec<_> = data["history"]["day"].as_array().unwrap().iter().map(|d| d["close"].as_f64().unwrap()).collect();
+        assert_eq!(closes, [1.0, 2.0, 3.0]);
+        let uris: Vec<_> = transport.requests().into_iter().map(|r| r.uri).collect();
+        assert_eq!(uris, [
+            "/markets/history?symbol=SPY&interval=daily&start=2022-01-01&end=2022-12-31",
+            "/markets/history?symbol=SPY&interval=daily&start=2023-01-01&end=2023-12-31",
+            "/markets/history?symbol=SPY&interval=daily&start=2024-01-01&end=2024-03-01",
+        ]);
+
+        // Weekly bars are requested in one go.
+        get_history_with(&transport, "SPY", HistoryInterval::Weekly, start, end, &HistoryOptions::default()).await.unwrap();
+        assert_eq!(transport.requests().len(), 4);
+        assert_eq!(year_ranges(start, NaiveDate::from_ymd_opt(2022, 12, 31).unwrap()).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_history_options() {
+        let day = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        assert_eq!(history_uri("SPY", HistoryInterval::Daily, day(1), day(5), Some(SessionFilter::Open)),
+            "/markets/history?symbol=SPY&interval=daily&start=2024-03-01&end=2024-03-05&session_filter=open");
+
+        let transport = MockTransport::new().with_response("/markets/history", StatusCode::OK,
+            r#"{"history":{"day":[{"date":"2024-03-01","close":100.0},{"date":"2024-03-04","close":99.0},{"date":"2024-03-05","close":101.0}]}}"#);
+        let options = HistoryOptions { session_filter: Some(SessionFilter::All), adj_close: Some(vec![(day(4), 2.0)]) };
+        let body = get_history_with(&transport, "SPY", HistoryInterval::Daily, day(1), day(5), &options).await.unwrap();
+        let data: Value = serde_json::from_str(&body).unwrap();
+        let closes: Vec<_> = data["history"]["day"].as_array().unwrap().iter()
+            .map(|d| (d["close"].as_f64().unwrap(), d["adj_close"].as_f64().unwrap())).collect();
+        assert_eq!(closes, [(100.0, 98.0), (99.0, 99.0), (101.0, 101.0)]);
+        assert!(transport.requests()[0].uri.ends_with("&session_filter=all"));
+
+        // Without dividends adj_close is close, and a single bar becomes a list.
+        let transport = MockTransport::new().with_response("/markets/history", StatusCode::OK, r#"{"history":{"day":{"date":"2024-03-01","close":100.0}}}"#);
+        let options = HistoryOptions { adj_close: Some(Vec::new()), ..Default::default() };
+        let body = get_history_with(&transport, "SPY", HistoryInterval::Daily, day(1), day(1), &options).await.unwrap();
+        assert_eq!(body, r#"{"history":{"day":[{"adj_close":100.0,"close":100.0,"date":"2024-03-01"}]}}"#);
+    }
+
+    #[tokio::test]
+    async fn test_history_multi_interval() {
+        let daily = r#"{"history":{"day":[{"date":"2024-03-01","high":101.0,"low":99.0},{"date":"2024-03-04","high":103.0,"low":100.0},{"date":"2024-03-05","high":102.0,"low":98.0}]}}"#;
+        let weekly = r#"{"history":{"day":[{"date":"2024-02-26","high":101.0,"low":99.0},{"date":"2024-03-04","high":103.0,"low":98.0}]}}"#;
+        let (start, end) = (NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), NaiveDate::from_ymd_opt(2024, 3, 5).unwrap());
+        let intervals = [HistoryInterval::Weekly, HistoryInterval::Daily];
+        let transport = MockTransport::new().with_response("/markets/history", StatusCode::OK, weekly).with_response("/markets/history", StatusCode::OK, daily);
+        let bodies = get_history_multi_interval_with(&transport, "SPY", &intervals, start, end, &HistoryOptions::default()).await.unwrap();
+        assert_eq!(bodies, [weekly, daily]);
+        let uris: Vec<_> = transport.requests().into_iter().map(|r| r.uri).collect();
+        assert!(uris[0].contains("interval=weekly") && uris[1].contains("interval=daily"));
+
+        // A weekly low above a daily low of the same week doesn't reconcile.
+        let weekly = weekly.replace(r#""low":98.0"#, r#""low":99.0"#);
+        let transport = MockTransport::new().with_response("/markets/history", StatusCode::OK, &weekly).with_response("/markets/history", StatusCode::OK, daily);
+        match get_history_multi_interval_with(&transport, "SPY", &intervals, start, end, &HistoryOptions::default()).await {
+            Err(Error::Parse(msg)) => assert_eq!(msg, "weekly bar of 2024-03-04 doesn't span the daily bar of 2024-03-05"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_quote() {
+        let transport = MockTransport::new().with_response("/markets/quotes", StatusCode::OK,
+            r#"{"quotes":{"quote":{"symbol":"SPX","description":"S&P 500 Index","exch":"","type":"index","last":5500.5,"bid":null,"ask":null,"bidexch":null,"askexch":null,"volume":0}}}"#);
+        let quote = get_quote_with(&transport, "$spx").await.unwrap();
+        assert!(quote.is_index());
+        assert_eq!((quote.symbol.as_str(), quote.last, quote.bid, quote.exchange, quote.bid_exchange), ("SPX", Some(5500.5), None, None, None));
+        assert_eq!(quote.extra["volume"], json!(0));
+        assert_eq!(transport.requests()[0].uri, "/markets/quotes?symbols=SPX");
+
+        let quotes = parse_quotes(r#"{"quotes":{"quote":[{"symbol":"SPY","type":"etf","exch":"P","bid":500.0,"ask":500.1,"bidexch":"Q","askexch":"P"},{"symbol":"X","type":"future"}]}}"#).unwrap();
+        assert_eq!((quotes[0].kind, quotes[0].bid, quotes[0].exchange.as_deref()), (QuoteType::Etf, Some(500.0), Some("P")));
+        assert_eq!(quotes[1].kind, QuoteType::Other);
+
+        let transport = MockTransport::new().with_response("/markets/quotes", StatusCode::OK, r#"{"quotes":{"unmatched_symbols":{"symbol":"NOPE"}}}"#);
+        assert!(matches!(get_quote_with(&transport, "NOPE").await, Err(Error::InvalidSymbol(_))));
+        assert!(matches!(get_quote_with(&transport, "$").await, Err(Error::InvalidSymbol(_))));
+    }
+
+    #[tokio::test]
+    async fn test_history_range() {
+        let transport = MockTransport::new().with_response("/markets/history", StatusCode::OK, r#"{"history":null}"#);
+        let (start, end) = (NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(), NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+        match get_history_with(&transport, "SPY", HistoryInterval::Daily, start, end, &HistoryOptions::default()).await {
+            Err(Error::InvalidRequest(msg)) => assert_eq!(msg, "Start date must be before or equal to end date"),
+            other => panic!("{:?}", other),
+        }
+        assert!(transport.requests().is_empty());
+        // A single day is a valid range.
+        assert!(get_history_with(&transport, "SPY", HistoryInterval::Daily, start, start, &HistoryOptions::default()).await.is_ok());
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
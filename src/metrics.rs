@@ -0,0 +1,184 @@
+use std::{collections::HashMap, sync::{Mutex, OnceLock}, time::{Duration, Instant}};
+
+/// Counters for monitoring a running client, see [`metrics`].
+/// With the `metrics-facade` feature everything is also reported through the `metrics` crate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Time since the first thing was recorded.
+    pub uptime: Duration,
+    pub messages: u64,
+    /// Messages received in the last complete second.
+    pub messages_per_sec: u64,
+    pub symbol_messages: HashMap<String, u64>,
+    /// Time spent in the stream handler per message.
+    pub fanout: LatencyStats,
+    pub reconnects: u64,
+    /// REST calls including retries, measured per attempt.
+    pub rest: LatencyStats,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    pub fn mean(&self) -> Duration {
+        // Divide in nanos, since Duration only divides by u32 and the count can pass u32::MAX.
+        if self.count == 0 { Duration::ZERO } else { Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64) }
+    }
+
+    fn record(&mut self, elapsed:Duration) {
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+}
+
+struct Metrics {
+    started: Instant,
+    messages: u64,
+    second: u64,
+    this_second: u64,
+    last_second: u64,
+    symbol_messages: HashMap<String, u64>,
+    fanout: LatencyStats,
+    reconnects: u64,
+    rest: LatencyStats,
+}
+
+impl Metrics {
+    fn new(started:Instant) -> Self {
+        Self {
+            started, messages: 0, second: 0, this_second: 0, last_second: 0,
+            symbol_messages: HashMap::new(), fanout: LatencyStats::default(), reconnects: 0, rest: LatencyStats::default(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "stream"), allow(dead_code))]
+    fn record_message(&mut self, now:Instant, symbol:Option<&str>, fanout:Duration) {
+        let second = now.saturating_duration_since(self.started).as_secs();
+        self.roll(second);
+        self.this_second += 1;
+        self.messages += 1;
+        if let Some(symbol) = symbol {
+            // Only allocate the key the first time a symbol is seen, this runs for every message.
+            match self.symbol_messages.get_mut(symbol) {
+                Some(count) => *count += 1,
+                None => { self.symbol_messages.insert(symbol.to_string(), 1); },
+            }
+        }
+        self.fanout.record(fanout);
+    }
+
+    fn roll(&mut self, second:u64) {
+        if second != self.second {
+            self.last_second = if second == self.second + 1 { self.this_second } else { 0 };
+            self.this_second = 0;
+            self.second = second;
+        }
+    }
+
+    fn snapshot(&mut self, now:Instant) -> MetricsSnapshot {
+        let uptime = now.saturating_duration_since(self.started);
+        self.roll(uptime.as_secs());
+        MetricsSnapshot {
+            uptime,
+            messages: self.messages,
+            messages_per_sec: self.last_second,
+            symbol_messages: self.symbol_messages.clone(),
+            fanout: self.fanout,
+            reconnects: self.reconnects,
+            rest: self.rest,
+        }
+    }
+}
+
+fn global() -> &'static Mutex<Metrics> {
+    static METRICS: OnceLock<Mutex<Metrics>> = OnceLock::new();
+    METRICS.get_or_init(|| Mutex::new(Metrics::new(Instant::now())))
+}
+
+/// Current values of the counters recorded across all streams and REST calls in this process.
+pub fn metrics() -> MetricsSnapshot {
+    global().lock().unwrap().snapshot(Instant::now())
+}
+
+#[cfg_attr(not(feature = "stream"), allow(dead_code))]
+pub(crate) fn record_message(symbol:Option<&str>, fanout:Duration) {
+    global().lock().unwrap().record_message(Instant::now(), symbol, fanout);
+    #[cfg(feature = "metrics-facade")]
+    {
+        ::metrics::counter!("tradier_stream_messages_total").increment(1);
+        ::metrics::histogram!("tradier_stream_fanout_seconds").record(fanout.as_secs_f64());
+    }
+}
+
+#[cfg_attr(not(feature = "stream"), allow(dead_code))]
+pub(crate) fn record_reconnect() {
+    global().lock().unwrap().reconnects += 1;
+    #[cfg(feature = "metrics-facade")]
+    ::metrics::counter!("tradier_stream_reconnects_total").increment(1);
+}
+
+pub(crate) fn record_rest(path:&str, elapsed:Duration) {
+    global().lock().unwrap().rest.record(elapsed);
+    #[cfg(feature = "metrics-facade")]
+    ::metrics::histogram!("tradier_rest_seconds", "path" => path_label(path)).record(elapsed.as_secs_f64());
+    #[cfg(not(feature = "metrics-facade"))]
+    let _ = path;
+}
+
+/// The path with account and order ids replaced by placeholders, so the label has a bounded set of values.
+#[cfg(feature = "metrics-facade")]
+fn path_label(path:&str) -> String {
+    let mut previous = "";
+    path.split('/').map(|segment| {
+        let label = match previous {
+            "accounts" if !segment.is_empty() => "{account_id}",
+            "orders" if !segment.is_empty() => "{order_id}",
+            _ => segment,
+        };
+        previous = segment;
+        label
+    }).collect::<Vec<_>>().join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let start = Instant::now();
+        let mut m = Metrics::new(start);
+        m.record_message(start, Some("SPY"), Duration::from_micros(10));
+        m.record_message(start + Duration::from_millis(500), Some("SPY"), Duration::from_micros(30));
+        m.record_message(start + Duration::from_millis(1200), None, Duration::from_micros(20));
+
+        let s = m.snapshot(start + Duration::from_millis(1500));
+        assert_eq!((s.messages, s.messages_per_sec), (3, 2));
+        assert_eq!(s.symbol_messages["SPY"], 2);
+        assert_eq!(s.fanout.mean(), Duration::from_micros(20));
+        assert_eq!(s.fanout.max, Duration::from_micros(30));
+        assert_eq!(m.snapshot(start + Duration::from_millis(2100)).messages_per_sec, 1);
+        assert_eq!(m.snapshot(start + Duration::from_secs(5)).messages_per_sec, 0);
+    }
+
+    #[cfg(feature = "metrics-facade")]
+    #[test]
+    fn test_path_label() {
+        assert_eq!(path_label("/accounts/VA123/orders/456"), "/accounts/{account_id}/orders/{order_id}");
+        assert_eq!(path_label("/accounts/VA123/orders"), "/accounts/{account_id}/orders");
+        assert_eq!(path_label("/markets/quotes"), "/markets/quotes");
+    }
+
+    #[test]
+    fn test_mean_large_count() {
+        let stats = LatencyStats { count: u32::MAX as u64 + 1, total: Duration::from_micros(u32::MAX as u64 + 1), max: Duration::ZERO };
+        assert_eq!(stats.mean(), Duration::from_micros(1));
+        assert_eq!(LatencyStats::default().mean(), Duration::ZERO);
+    }
+}
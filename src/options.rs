@@ -0,0 +1,257 @@
+use chrono::{Datelike, NaiveDate, Weekday};
+
+/// Max length of the root symbol portion of an OCC option symbol.
+pub const OCC_ROOT_LEN: usize = 6;
+/// Length of a fully padded OCC option symbol: 6 root + 6 date + 1 right + 8 strike.
+pub const OCC_SYMBOL_LEN: usize = 21;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OptionRight {
+    Call,
+    Put,
+}
+
+impl OptionRight {
+    pub fn to_char(self) -> char {
+        match self {
+            OptionRight::Call => 'C',
+            OptionRight::Put => 'P',
+        }
+    }
+
+    pub fn from_char(c:char) -> Option<Self> {
+        match c {
+            'C' | 'c' => Some(OptionRight::Call),
+            'P' | 'p' => Some(OptionRight::Put),
+            _ => None,
+        }
+    }
+}
+
+/// When an option's settlement value is determined on expiration day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Settlement {
+    /// From the opening prices, trading ends the day before. Standard monthly index options, eg. SPX, NDX, RUT.
+    Am,
+    /// From the closing price, trading through expiration day. Equities, ETFs and index weeklies, eg. SPXW, NDXP.
+    Pm,
+}
+
+/// Index option roots that settle AM. VIX weeklies settle AM like the monthlies.
+const AM_SETTLED_ROOTS: [&str; 6] = ["SPX", "NDX", "RUT", "DJX", "VIX", "VIXW"];
+
+/// Index option roots that differ from their underlying symbol.
+const INDEX_ROOTS: [(&str, &str); 5] = [("SPXW", "SPX"), ("SPXPM", "SPX"), ("NDXP", "NDX"), ("RUTW", "RUT"), ("VIXW", "VIX")];
+
+/// Settlement type for an option root. Anything not a known AM settled index root is PM settled.
+pub fn settlement_for_root(root:&str) -> Settlement {
+    if AM_SETTLED_ROOTS.contains(&root.trim()) { Settlement::Am } else { Settlement::Pm }
+}
+
+/// The underlying symbol for an option root, eg. SPX for SPXW. Other roots are returned as is.
+/// Useful for grouping an index's option roots, since the chain for SPX includes both SPX and SPXW.
+pub fn root_underlying(root:&str) -> &str {
+    let root = root.trim();
+    INDEX_ROOTS.iter().find(|(r, _)| *r == root).map_or(root, |(_, underlying)| underlying)
+}
+
+/// The parts that make up an option contract symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionSpec {
+    pub underlying: String,
+    pub expiration: NaiveDate,
+    pub right: OptionRight,
+    pub strike: f64,
+}
+
+impl OptionSpec {
+    pub fn new(underlying:&str, expiration:NaiveDate, right:OptionRight, strike:f64) -> Self {
+        Self { underlying: underlying.to_string(), expiration, right, strike }
+    }
+
+    /// See [`occ_option_symbol`].
+    pub fn to_occ_symbol(&self) -> Option<String> {
+        occ_option_symbol(&self.underlying, self.expiration, self.right, self.strike)
+    }
+
+    /// Calendar days to expiration, 0 on expiration day and negative after.
+    pub fn dte(&self, as_of:NaiveDate) -> i64 {
+        (self.expiration - as_of).num_days()
+    }
+
+    /// True once the option can no longer trade: after expiration day for PM settled options, and from expiration
+    /// day for AM settled ones, which stop trading the day before. See [`OptionSpec::last_trade_date`].
+    pub fn is_expired(&self, as_of:NaiveDate) -> bool {
+        as_of > self.last_trade_date()
+    }
+
+    /// Settlement type by root, so SPX is AM and SPXW is PM. underlying is the OCC root.
+    pub fn settlement(&self) -> Settlement {
+        settlement_for_root(&self.underlying)
+    }
+
+    /// The underlying symbol for this option's root, see [`root_underlying`].
+    pub fn root_underlying(&self) -> &str {
+        root_underlying(&self.underlying)
+    }
+
+    /// Last day the option trades: the expiration date for PM settled options and the previous weekday for AM
+    /// settled ones. Exchange holidays aren't accounted for.
+    pub fn last_trade_date(&self) -> NaiveDate {
+        match self.settlement() {
+            Settlement::Pm => self.expiration,
+            Settlement::Am => {
+                let mut date = self.expiration.pred_opt().unwrap_or(self.expiration);
+                while matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+                    date = date.pred_opt().unwrap_or(date);
+                }
+                date
+            },
+        }
+    }
+}
+
+/// Formats the given parts into the 21 character OCC symbol, eg. `SPY   240419C00500000`.
+/// The root is space padded to 6 characters and the strike is in thousandths padded to 8 digits.
+/// Returns None if any part can't be represented: root empty or longer than 6 characters,
+/// expiration year outside 2000..=2099, or strike negative or too large.
+pub fn occ_option_symbol(underlying:&str, expiration:NaiveDate, right:OptionRight, strike:f64) -> Option<String> {
+    let root = underlying.trim();
+    if root.is_empty() || root.len() > OCC_ROOT_LEN || !root.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    if !(2000..=2099).contains(&expiration.year()) {
+        return None;
+    }
+    let strike_thousandths = (strike * 1000.0).round();
+    if !(0.0..=99_999_999.0).contains(&strike_thousandths) {
+        return None;
+    }
+    Some(format!("{:<6}{}{}{:08}",
+        root.to_ascii_uppercase(),
+        expiration.format("%y%m%d"),
+        right.to_char(),
+        strike_thousandths as u32))
+}
+
+/// Parses an OCC option symbol. Accepts both the padded form (`SPY   240419C00500000`)
+/// and the compact form Tradier uses (`SPY240419C00500000`).
+pub fn parse_occ_option_symbol(symbol:&str) -> Option<OptionSpec> {
+    let symbol = symbol.trim();
+    // Date, right, and strike are fixed width at the end, so split from the end.
+    if symbol.len() < 16 || !symbol.is_ascii() {
+        return None;
+    }
+    let (root, rest) = symbol.split_at(symbol.len() - 15);
+    let root = root.trim_end();
+    if root.is_empty() || root.len() > OCC_ROOT_LEN || !root.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    // OCC years are always 20yy; chrono's %y would read 69 and above as 19yy.
+    let date_str = &rest[0..6];
+    if !date_str.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let num = |range:std::ops::Range<usize>| date_str[range].parse::<u32>().ok();
+    let expiration = NaiveDate::from_ymd_opt(2000 + num(0..2)? as i32, num(2..4)?, num(4..6)?)?;
+    let right = OptionRight::from_char(rest[6..7].chars().next()?)?;
+    let strike_str = &rest[7..15];
+    if !strike_str.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let strike = strike_str.parse::<u32>().ok()? as f64 / 1000.0;
+    Some(OptionSpec { underlying: root.to_string(), expiration, right, strike })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y:i32, m:u32, d:u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_to_occ_symbol() {
+        let spec = OptionSpec::new("SPY", date(2024, 4, 19), OptionRight::Call, 500.0);
+        assert_eq!(spec.to_occ_symbol().unwrap(), "SPY   240419C00500000");
+        assert_eq!(spec.to_occ_symbol().unwrap().len(), OCC_SYMBOL_LEN);
+        assert_eq!(occ_option_symbol("spxw", date(2025, 12, 31), OptionRight::Put, 4512.5).unwrap(), "SPXW  251231P04512500");
+    }
+
+    #[test]
+    fn test_to_occ_symbol_invalid() {
+        assert_eq!(occ_option_symbol("", date(2024, 4, 19), OptionRight::Call, 500.0), None);
+        assert_eq!(occ_option_symbol("TOOLONG", date(2024, 4, 19), OptionRight::Call, 500.0), None);
+        assert_eq!(occ_option_symbol("SPY", date(1999, 4, 19), OptionRight::Call, 500.0), None);
+        assert_eq!(occ_option_symbol("SPY", date(2024, 4, 19), OptionRight::Call, -1.0), None);
+        assert_eq!(occ_option_symbol("SPY", date(2024, 4, 19), OptionRight::Call, 100_000.0), None);
+    }
+
+    #[test]
+    fn test_parse_occ_option_symbol() {
+        let spec = parse_occ_option_symbol("SPY240419C00500000").unwrap();
+        assert_eq!(spec, OptionSpec::new("SPY", date(2024, 4, 19), OptionRight::Call, 500.0));
+        let spec = parse_occ_option_symbol("SPXW  251231P04512500").unwrap();
+        assert_eq!(spec, OptionSpec::new("SPXW", date(2025, 12, 31), OptionRight::Put, 4512.5));
+        assert_eq!(parse_occ_option_symbol("SPY"), None);
+        assert_eq!(parse_occ_option_symbol("SPY240419X00500000"), None);
+        assert_eq!(parse_occ_option_symbol("SPY241319C00500000"), None);
+        assert_eq!(parse_occ_option_symbol("SP-Y240419C00500000"), None);
+        assert_eq!(parse_occ_option_symbol("SP Y  240419C00500000"), None);
+        assert_eq!(parse_occ_option_symbol("SPY24-419C00500000"), None);
+        assert_eq!(parse_occ_option_symbol("SPY990115C00500000").unwrap().expiration, date(2099, 1, 15));
+    }
+
+    #[test]
+    fn test_dte() {
+        let spec = OptionSpec::new("SPY", NaiveDate::from_ymd_opt(2024, 4, 19).unwrap(), OptionRight::Call, 500.0);
+        assert_eq!(spec.dte(NaiveDate::from_ymd_opt(2024, 4, 12).unwrap()), 7);
+        assert_eq!(spec.dte(NaiveDate::from_ymd_opt(2024, 4, 19).unwrap()), 0);
+        assert!(!spec.is_expired(NaiveDate::from_ymd_opt(2024, 4, 19).unwrap()));
+        assert!(spec.is_expired(NaiveDate::from_ymd_opt(2024, 4, 20).unwrap()));
+    }
+
+    #[test]
+    fn test_index_roots() {
+        let spx = parse_occ_option_symbol("SPX   240920C05500000").unwrap();
+        let spxw = parse_occ_option_symbol("SPXW  240920C05500000").unwrap();
+        assert_eq!((spx.settlement(), spxw.settlement()), (Settlement::Am, Settlement::Pm));
+        assert_eq!((spx.root_underlying(), spxw.root_underlying()), ("SPX", "SPX"));
+        assert_eq!(spx.last_trade_date(), date(2024, 9, 19));
+        assert_eq!(spxw.last_trade_date(), date(2024, 9, 20));
+        assert_eq!(root_underlying("NDXP"), "NDX");
+        assert_eq!(root_underlying("SPY"), "SPY");
+        assert_eq!(settlement_for_root("XSP"), Settlement::Pm);
+        assert_eq!(OptionSpec::new("VIX", date(2024, 9, 18), OptionRight::Put, 15.0).last_trade_date(), date(2024, 9, 17));
+        // The weekend is skipped if the expiration is a Monday.
+        assert_eq!(OptionSpec::new("RUT", date(2024, 9, 16), OptionRight::Put, 2000.0).last_trade_date(), date(2024, 9, 13));
+    }
+
+    #[test]
+    fn test_is_expired_am() {
+        let spx = OptionSpec::new("SPX", date(2024, 9, 20), OptionRight::Call, 5500.0);
+        let spxw = OptionSpec::new("SPXW", date(2024, 9, 20), OptionRight::Call, 5500.0);
+        assert!(!spx.is_expired(date(2024, 9, 19)));
+        assert!(spx.is_expired(date(2024, 9, 20)));
+        assert!(!spxw.is_expired(date(2024, 9, 20)));
+        assert!(spxw.is_expired(date(2024, 9, 21)));
+    }
+
+    #[test]
+    fn test_occ_round_trip() {
+        let specs = [
+            OptionSpec::new("SPY", date(2024, 4, 19), OptionRight::Call, 500.0),
+            OptionSpec::new("AAPL", date(2030, 1, 18), OptionRight::Put, 172.5),
+            OptionSpec::new("BRKB", date(2024, 6, 21), OptionRight::Call, 0.5),
+            OptionSpec::new("NDX", date(2024, 3, 15), OptionRight::Put, 18225.0),
+            OptionSpec::new("GME1", date(2070, 12, 19), OptionRight::Call, 25.0),
+            OptionSpec::new("XYZ", date(2099, 6, 18), OptionRight::Put, 99_999.999),
+        ];
+        for spec in specs {
+            let sym = spec.to_occ_symbol().unwrap();
+            assert_eq!(parse_occ_option_symbol(&sym).unwrap(), spec);
+            assert_eq!(parse_occ_option_symbol(&sym.replace(' ', "")).unwrap(), spec);
+        }
+    }
+}
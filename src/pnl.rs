@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+use crate::{data::Handler, events::StreamEvent, margin::CONTRACT_MULTIPLIER, options::parse_occ_option_symbol, strategies::mid_price};
+
+/// Which price positions are valued at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    /// No mark without both a bid and an ask, see [`mid_price`]. Use Conservative to value positions without a bid.
+    Mid,
+    Last,
+    /// What the position could be closed at now: bid for longs, ask for shorts.
+    Conservative,
+}
+
+/// A held position. quantity is negative for shorts, average_price is per share.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub symbol: String,
+    pub quantity: f64,
+    pub average_price: f64,
+    pub multiplier: f64,
+}
+
+impl Position {
+    /// Options (OCC symbols) get the standard contract multiplier, anything else 1.
+    pub fn new(symbol:&str, quantity:f64, average_price:f64) -> Self {
+        let multiplier = if parse_occ_option_symbol(symbol).is_some() { CONTRACT_MULTIPLIER } else { 1.0 };
+        Self { symbol: symbol.to_string(), quantity, average_price, multiplier }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Prices {
+    bid: Option<f64>,
+    ask: Option<f64>,
+    last: Option<f64>,
+}
+
+/// Sent whenever a position's unrealized P&L changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PnlUpdate {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub mark: f64,
+    pub unrealized: f64,
+    /// Sum over all positions that have a mark.
+    pub account_unrealized: f64,
+}
+
+/// Consumes typed stream events for the position symbols and keeps live unrealized P&L.
+/// Stream [`PnlTracker::symbols`] to it and listen with [`PnlTracker::subscribe`].
+pub struct PnlTracker {
+    positions: HashMap<String, Position>,
+    prices: HashMap<String, Prices>,
+    unrealized: HashMap<String, (f64, f64)>,
+    mark: Mark,
+    updates: broadcast::Sender<PnlUpdate>,
+}
+
+impl PnlTracker {
+    pub fn new(positions:Vec<Position>, mark:Mark) -> Self {
+        Self {
+            positions: positions.into_iter().map(|p| (p.symbol.clone(), p)).collect(),
+            prices: HashMap::new(),
+            unrealized: HashMap::new(),
+            mark,
+            updates: broadcast::channel(256).0,
+        }
+    }
+
+    /// Symbols to subscribe to.
+    pub fn symbols(&self) -> Vec<&str> {
+        let mut symbols = self.positions.keys().map(String::as_str).collect::<Vec<_>>();
+        symbols.sort_unstable();
+        symbols
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PnlUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Mark price and unrealized P&L for the position, None until a usable price has arrived.
+    pub fn position_pnl(&self, symbol:&str) -> Option<(f64, f64)> {
+        self.unrealized.get(symbol).copied()
+    }
+
+    pub fn account_unrealized(&self) -> f64 {
+        self.unrealized.values().map(|(_, pnl)| pnl).sum()
+    }
+
+    fn mark_price(&self, position:&Position, prices:&Prices) -> Option<f64> {
+        match self.mark {
+            Mark::Mid => mid_price(prices.bid?, prices.ask?),
+            Mark::Last => prices.last,
+            Mark::Conservative if position.quantity >= 0.0 => prices.bid,
+            Mark::Conservative => prices.ask,
+        }
+    }
+}
+
+impl Handler<StreamEvent> for PnlTracker {
+    fn on_data(&mut self, timestamp:DateTime<Utc>, data:StreamEvent) {
+        let Some(symbol) = data.symbol() else { return };
+        let Some(position) = self.positions.get(symbol) else { return };
+        let prices = self.prices.entry(symbol.to_string()).or_default();
+        match &data {
+            StreamEvent::Quote(q) => {
+                prices.bid = Some(q.bid);
+                prices.ask = Some(q.ask);
+            },
+            StreamEvent::Trade(t) | StreamEvent::Tradex(t) => prices.last = Some(t.last),
+            _ => return,
+        }
+        let prices = *prices;
+        let Some(mark) = self.mark_price(position, &prices) else { return };
+        let unrealized = (mark - position.average_price) * position.quantity * position.multiplier;
+        if self.unrealized.insert(symbol.to_string(), (mark, unrealized)) == Some((mark, unrealized)) {
+            return;
+        }
+        // Only fails when there are no receivers.
+        let _ = self.updates.send(PnlUpdate {
+            timestamp, symbol: symbol.to_string(), mark, unrealized, account_unrealized: self.account_unrealized(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::parse_event;
+
+    fn quote(symbol:&str, bid:f64, ask:f64) -> StreamEvent {
+        parse_event(&format!(r#"{{"type":"quote","symbol":"{}","bid":{},"bidsz":1,"biddate":"0","ask":{},"asksz":1,"askdate":"0"}}"#, symbol, bid, ask)).unwrap()
+    }
+
+    #[test]
+    fn test_pnl() {
+        let positions = vec![Position::new("SPY", 10.0, 500.0), Position::new("SPY240621C00500000", -2.0, 5.0)];
+        assert_eq!(positions[1].multiplier, 100.0);
+        let mut t = PnlTracker::new(positions.clone(), Mark::Mid);
+        let mut rx = t.subscribe();
+        assert_eq!(t.symbols(), ["SPY", "SPY240621C00500000"]);
+
+        t.on_data(DateTime::<Utc>::default(), quote("SPY", 501.0, 501.2));
+        assert_eq!(t.position_pnl("SPY").map(|p| p.0), Some(501.1));
+        t.on_data(DateTime::<Utc>::default(), quote("SPY240621C00500000", 5.5, 5.7));
+        assert!((t.account_unrealized() - (11.0 - 120.0)).abs() < 1e-6);
+        t.on_data(DateTime::<Utc>::default(), quote("SPY240621C00500000", 5.5, 5.7));
+        t.on_data(DateTime::<Utc>::default(), quote("QQQ", 1.0, 2.0));
+        assert_eq!(rx.try_recv().unwrap().symbol, "SPY");
+        assert!((rx.try_recv().unwrap().account_unrealized - (11.0 - 120.0)).abs() < 1e-6);
+        assert!(rx.try_recv().is_err());
+
+        // No bid, so no mid to mark at and the previous mark stays.
+        t.on_data(DateTime::<Utc>::default(), quote("SPY240621C00500000", 0.0, 5.7));
+        assert_eq!(t.position_pnl("SPY240621C00500000").map(|p| p.0), Some(5.6));
+
+        let mut t = PnlTracker::new(positions, Mark::Conservative);
+        t.on_data(DateTime::<Utc>::default(), quote("SPY240621C00500000", 5.5, 5.7));
+        assert_eq!(t.position_pnl("SPY240621C00500000").map(|p| p.0), Some(5.7));
+    }
+}
@@ -0,0 +1,94 @@
+//! Quote polling over REST, for accounts that can't stream, eg. sandbox tokens.
+
+use chrono::Utc;
+use futures_util::Stream;
+use std::{sync::Arc, time::Duration};
+use tokio::time::{interval, MissedTickBehavior};
+use crate::{data::{snapshot_messages, Handler, MarketData}, events::{StreamEvent, TypedHandler}, http::HttpTransport,
+    markets::{get_quotes, get_quotes_with}, stream::{market_data_channel_with, DeliveryPolicy}, throttle::warn_throttled};
+
+/// Polls `/markets/quotes` for a symbol set and passes each quote to the handler as the same JSON the websocket
+/// sends, marked as a snapshot, so a handler or [`crate::stream::market_data_channel`] works with either source.
+#[derive(Clone)]
+pub struct PollingQuoteSource {
+    symbols: Vec<String>,
+    interval: Duration,
+    transport: Option<Arc<dyn HttpTransport>>,
+}
+
+impl PollingQuoteSource {
+    pub fn new(symbols:&[&str], interval:Duration) -> Self {
+        Self { symbols: symbols.iter().map(|s| s.to_string()).collect(), interval, transport: None }
+    }
+
+    /// Polls through this transport instead of the global one set with [`crate::http::set_transport`].
+    pub fn transport(mut self, transport:Arc<dyn HttpTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Polls every interval until the handler is done. A failed poll is warned about and the next one goes ahead
+    /// as scheduled. A poll that takes longer than interval delays the following ones rather than bunching them.
+    pub async fn run<H:Handler<String>>(&self, mut handler:H) {
+        let symbols: Vec<&str> = self.symbols.iter().map(String::as_str).collect();
+        let mut ticks = interval(self.interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let result = match &self.transport {
+                Some(transport) => get_quotes_with(&**transport, &symbols, false).await,
+                None => get_quotes(&symbols, false).await,
+            };
+            let body = match result {
+                Ok(body) => body,
+                Err(e) => {
+                    warn_throttled("polling:quotes", &format!("Error polling quotes: {}", e));
+                    continue;
+                },
+            };
+            let now = Utc::now();
+            for payload in snapshot_messages(&body) {
+                handler.on_data(now, payload);
+                if handler.is_done() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Polls on a spawned task and returns the parsed quotes as a stream, like [`crate::stream::stream_symbols`].
+/// Dropping the stream stops the polling after the next quote arrives. Must be called from within a tokio runtime.
+pub fn poll_symbols(symbols:&[&str], interval:Duration) -> impl Stream<Item = MarketData<StreamEvent>> {
+    let (handler, stream) = market_data_channel_with(usize::MAX, DeliveryPolicy::Unbounded);
+    let source = PollingQuoteSource::new(symbols, interval);
+    tokio::spawn(async move { source.run(TypedHandler::new(handler)).await });
+    stream
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{http::MockTransport, stream::market_data_channel};
+    use futures_util::StreamExt;
+    use reqwest::StatusCode;
+
+    #[tokio::test]
+    async fn test_polling_quote_source() {
+        let transport = Arc::new(MockTransport::new()
+            .with_response("/markets/quotes", StatusCode::BAD_REQUEST, "bad")
+            .with_response("/markets/quotes", StatusCode::OK, r#"{"quotes":{"quote":[{"symbol":"SPY","bid":500.0,"ask":500.1},{"symbol":"QQQ","bid":400.0,"ask":400.1}]}}"#));
+        let source = PollingQuoteSource::new(&["SPY", "QQQ"], Duration::from_millis(5)).transport(transport.clone());
+        let (handler, stream) = market_data_channel();
+        let polling = tokio::spawn(async move { source.run(TypedHandler::new(handler)).await });
+
+        let quotes: Vec<_> = stream.take(3).map(|md| match md.data {
+            StreamEvent::Quote(q) => (q.is_snapshot(), q.symbol, q.bid),
+            other => panic!("{:?}", other),
+        }).collect().await;
+        assert_eq!(quotes, [(true, "SPY".to_string(), 500.0), (true, "QQQ".to_string(), 400.0), (true, "SPY".to_string(), 500.0)]);
+        // Dropping the stream ends the run on the next quote.
+        polling.await.unwrap();
+        assert!(transport.requests().len() >= 2);
+    }
+}
@@ -0,0 +1,17 @@
+//! The commonly used types and functions, for `use rust_tradier::prelude::*;`.
+
+pub use crate::auth::{Credentials, TokenProvider};
+pub use crate::data::{ConnectionEvent, Handler, MarketData, StreamConfig};
+#[cfg(feature = "stream")]
+pub use crate::data::{run_async, run_async_with};
+pub use crate::error::{ApiError, ApiErrorReason, Error};
+pub use crate::events::{QuoteEvent, StreamEvent, SummaryEvent, TimesaleEvent, TradeEvent, TypedHandler};
+pub use crate::http::{set_credentials, set_transport, tradier_get, tradier_post, tradier_request, ApiVersion, RequestOptions, RetryPolicy};
+pub use crate::options::{parse_occ_option_symbol, OptionRight, OptionSpec};
+pub use crate::polling::{poll_symbols, PollingQuoteSource};
+pub use crate::state::{MarketState, MarketStateStore};
+pub use crate::stream::{market_data_channel, DeliveryPolicy, MarketDataStream};
+#[cfg(feature = "stream")]
+pub use crate::stream::stream_symbols;
+pub use crate::symbol::Symbol;
+pub use crate::watchdog::FeedHealth;
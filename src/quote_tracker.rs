@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use crate::events::StreamEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuoteField {
+    Bid,
+    BidSize,
+    Ask,
+    AskSize,
+    Last,
+}
+
+/// A field that changed since the previous quote or trade for the symbol. old is None the first time it's seen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteChange {
+    pub symbol: String,
+    pub field: QuoteField,
+    pub old: Option<f64>,
+    pub new: f64,
+}
+
+impl QuoteChange {
+    /// new - old, or None for the first value.
+    pub fn delta(&self) -> Option<f64> {
+        self.old.map(|old| self.new - old)
+    }
+}
+
+/// Remembers the last bid, ask and last price per symbol and reports only what changed,
+/// so a wide watchlist can be reduced to the updates that matter.
+#[derive(Debug, Default)]
+pub struct QuoteTracker {
+    last: HashMap<String, HashMap<QuoteField, f64>>,
+}
+
+impl QuoteTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the changed fields from quote and trade events. Other events change nothing.
+    pub fn update(&mut self, event:&StreamEvent) -> Vec<QuoteChange> {
+        match event {
+            StreamEvent::Quote(q) => self.update_fields(&q.symbol, &[
+                (QuoteField::Bid, q.bid),
+                (QuoteField::BidSize, q.bid_size as f64),
+                (QuoteField::Ask, q.ask),
+                (QuoteField::AskSize, q.ask_size as f64),
+            ]),
+            StreamEvent::Trade(t) | StreamEvent::Tradex(t) => self.update_fields(&t.symbol, &[(QuoteField::Last, t.last)]),
+            _ => Vec::new(),
+        }
+    }
+
+    /// For polled snapshots or any other source of values.
+    pub fn update_fields(&mut self, symbol:&str, fields:&[(QuoteField, f64)]) -> Vec<QuoteChange> {
+        let last = self.last.entry(symbol.to_string()).or_default();
+        fields.iter().filter_map(|&(field, new)| {
+            let old = last.insert(field, new);
+            (old != Some(new)).then(|| QuoteChange { symbol: symbol.to_string(), field, old, new })
+        }).collect()
+    }
+
+    pub fn get(&self, symbol:&str, field:QuoteField) -> Option<f64> {
+        self.last.get(symbol)?.get(&field).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::parse_event;
+
+    #[test]
+    fn test_quote_tracker() {
+        let quote = |bid:f64, ask:f64| parse_event(&format!(
+            r#"{{"type":"quote","symbol":"SPY","bid":{},"bidsz":10,"biddate":"0","ask":{},"asksz":5,"askdate":"0"}}"#, bid, ask)).unwrap();
+        let mut t = QuoteTracker::new();
+        assert_eq!(t.update(&quote(500.0, 500.1)).len(), 4);
+        assert!(t.update(&quote(500.0, 500.1)).is_empty());
+
+        let changes = t.update(&quote(500.0, 500.2));
+        assert_eq!(changes, [QuoteChange { symbol: "SPY".to_string(), field: QuoteField::Ask, old: Some(500.1), new: 500.2 }]);
+        assert!((changes[0].delta().unwrap() - 0.1).abs() < 1e-9);
+
+        let trade = parse_event(r#"{"type":"trade","symbol":"SPY","price":"500.1","size":"1","cvol":"1","date":"0","last":"500.1"}"#).unwrap();
+        assert_eq!(t.update(&trade)[0].delta(), None);
+        assert_eq!(t.get("SPY", QuoteField::Last), Some(500.1));
+        assert!(t.update(&StreamEvent::Other).is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::{fs::{File, OpenOptions}, io::{self, BufRead, BufReader, BufWriter, Write}, path::Path};
+use crate::{data::Handler, watchdog::FeedHealth};
+
+/// Handler that appends every raw message with its receive timestamp to a JSONL file,
+/// then passes it on to the wrapped handler.
+/// Each line is `{"ts":<receive time in epoch micros>,"msg":"<raw websocket text>"}`.
+pub struct Recorder<H> {
+    inner: H,
+    out: BufWriter<File>,
+}
+
+impl<H> Recorder<H> {
+    /// Opens (or creates) the file at path in append mode.
+    pub fn new<P:AsRef<Path>>(path:P, inner:H) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { inner, out: BufWriter::new(file) })
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Flushes and returns the wrapped handler.
+    pub fn into_inner(mut self) -> io::Result<H> {
+        self.out.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<H:Handler<String>> Handler<String> for Recorder<H> {
+    fn on_data(&mut self, timestamp:DateTime<Utc>, data:String) {
+        let line = json!({ "ts": timestamp.timestamp_micros(), "msg": data });
+        if let Err(e) = writeln!(self.out, "{}", line) {
+            println!("Error writing recorded message: {:?}", e);
+        }
+        self.inner.on_data(timestamp, data);
+    }
+
+    fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    fn on_feed_health(&mut self, health:FeedHealth) {
+        self.inner.on_feed_health(health)
+    }
+}
+
+/// Reads a file written by [`Recorder`] and feeds it back to a handler with the original receive timestamps.
+pub struct ReplaySource {
+    lines: io::Lines<BufReader<File>>,
+}
+
+impl ReplaySource {
+    pub fn open<P:AsRef<Path>>(path:P) -> io::Result<Self> {
+        Ok(Self { lines: BufReader::new(File::open(path)?).lines() })
+    }
+
+    /// Sends every recorded message to the handler in order. Returns the number of messages replayed.
+    pub fn replay<H:Handler<String>>(self, handler:&mut H) -> io::Result<usize> {
+        let mut count = 0;
+        for entry in self {
+            let (timestamp, data) = entry?;
+            handler.on_data(timestamp, data);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl Iterator for ReplaySource {
+    type Item = io::Result<(DateTime<Utc>, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(parse_line(&line));
+        }
+    }
+}
+
+fn parse_line(line:&str) -> io::Result<(DateTime<Utc>, String)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid recorded line: {}", line));
+    let mut value = serde_json::from_str::<Value>(line).map_err(|_| invalid())?;
+    let timestamp = value["ts"].as_i64()
+        .and_then(DateTime::from_timestamp_micros)
+        .ok_or_else(invalid)?;
+    match value["msg"].take() {
+        Value::String(data) => Ok((timestamp, data)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Collect(Vec<(DateTime<Utc>, String)>);
+
+    impl Handler<String> for Collect {
+        fn on_data(&mut self, timestamp:DateTime<Utc>, data:String) {
+            self.0.push((timestamp, data));
+        }
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let path = std::env::temp_dir().join(format!("rust-tradier-recorder-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let t1 = DateTime::from_timestamp_micros(1_712_000_000_123_456).unwrap();
+        let t2 = DateTime::from_timestamp_micros(1_712_000_001_000_000).unwrap();
+        let msgs = [
+            (t1, r#"{"type":"trade","symbol":"SPY","price":"500.1"}"#.to_string()),
+            (t2, "line1\nline2 \"quoted\"".to_string()),
+        ];
+
+        let mut rec = Recorder::new(&path, Collect::default()).unwrap();
+        for (ts, msg) in msgs.iter().cloned() {
+            rec.on_data(ts, msg);
+        }
+        assert_eq!(rec.into_inner().unwrap().0, msgs);
+
+        let mut replayed = Collect::default();
+        assert_eq!(ReplaySource::open(&path).unwrap().replay(&mut replayed).unwrap(), 2);
+        assert_eq!(replayed.0, msgs);
+
+        std::fs::write(&path, "not json\n").unwrap();
+        assert!(ReplaySource::open(&path).unwrap().replay(&mut replayed).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use reqwest::Method;
+use serde_json::Value;
+use std::{collections::{HashMap, HashSet}, future::Future};
+use crate::{error::Error, http::{tradier_request_with, HttpTransport, RequestOptions}, market_hours::{from_eastern, to_eastern}};
+
+/// Longest single sleep while waiting, so a suspended machine or a clock change is noticed within this long.
+const MAX_SLEEP: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Regular session times for US equities with optional holidays and early closes, eg. loaded from the
+/// Tradier market calendar. All times in and out are UTC; the Eastern conversion is done here.
+#[derive(Debug, Clone)]
+pub struct MarketSchedule {
+    open: NaiveTime,
+    close: NaiveTime,
+    holidays: HashSet<NaiveDate>,
+    early_closes: HashMap<NaiveDate, NaiveTime>,
+}
+
+impl Default for MarketSchedule {
+    fn default() -> Self {
+        Self {
+            open: NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            holidays: HashSet::new(),
+            early_closes: HashMap::new(),
+        }
+    }
+}
+
+impl MarketSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Eastern date the market is closed all day.
+    pub fn holiday(mut self, date:NaiveDate) -> Self {
+        self.holidays.insert(date);
+        self
+    }
+
+    /// Eastern date the market closes at the given Eastern time, eg. 13:00.
+    pub fn early_close(mut self, date:NaiveDate, close:NaiveTime) -> Self {
+        self.early_closes.insert(date, close);
+        self
+    }
+
+    /// Loads holidays and early closes for each (year, month) from `/markets/calendar`, eg. the current and next month.
+    pub async fn load(transport:&dyn HttpTransport, months:&[(i32, u32)]) -> Result<Self, Error> {
+        let mut schedule = Self::new();
+        for (year, month) in months {
+            let uri = format!("/markets/calendar?month={:02}&year={}", month, year);
+            let body = tradier_request_with(transport, Method::GET, &uri, &RequestOptions::default()).await?;
+            schedule = schedule.with_calendar(&body)?;
+        }
+        Ok(schedule)
+    }
+
+    /// Adds the holidays and early closes in a `/markets/calendar` response body. Closed weekdays are holidays and
+    /// open days ending before the regular close are early closes.
+    pub fn with_calendar(mut self, body:&str) -> Result<Self, Error> {
+        let invalid = || Error::Parse(format!("Market calendar: {}", body));
+        let data: Value = serde_json::from_str(body).map_err(|_| invalid())?;
+        let days = match &data["calendar"]["days"]["day"] {
+            Value::Array(list) => list.iter().collect(),
+            day @ Value::Object(_) => vec![day],
+            _ => return Err(invalid()),
+        };
+        for day in days {
+            let date = day["date"].as_str().and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()).ok_or_else(invalid)?;
+            match day["status"].as_str() {
+                Some("closed") if !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) => {
+                    self.holidays.insert(date);
+                },
+                Some("open") => {
+                    let end = day["open"]["end"].as_str().and_then(|t| NaiveTime::parse_from_str(t, "%H:%M").ok());
+                    if let Some(end) = end.filter(|end| *end < self.close) {
+                        self.early_closes.insert(date, end);
+                    }
+                },
+                _ => {},
+            }
+        }
+        Ok(self)
+    }
+
+    /// (open, close) in UTC for the Eastern date, None on weekends and holidays.
+    pub fn session(&self, date:NaiveDate) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        if matches!(date.weekday(), Weekday::Sat | Weekday::Sun) || self.holidays.contains(&date) {
+            return None;
+        }
+        let close = self.early_closes.get(&date).copied().unwrap_or(self.close);
+        Some((from_eastern(date.and_time(self.open)), from_eastern(date.and_time(close))))
+    }
+
+    pub fn is_open(&self, utc:NaiveDateTime) -> bool {
+        self.session(to_eastern(utc).date()).is_some_and(|(open, close)| utc >= open && utc < close)
+    }
+
+    /// The current session if open, otherwise the next one.
+    pub fn current_or_next_session(&self, utc:NaiveDateTime) -> (NaiveDateTime, NaiveDateTime) {
+        let mut date = to_eastern(utc).date();
+        loop {
+            if let Some((open, close)) = self.session(date) {
+                if utc < close {
+                    return (open, close);
+                }
+            }
+            date += Duration::days(1);
+        }
+    }
+
+    /// Start of the next session, or utc itself if the market is open.
+    pub fn next_open(&self, utc:NaiveDateTime) -> NaiveDateTime {
+        self.current_or_next_session(utc).0.max(utc)
+    }
+
+    /// End of the current session, or of the next one if the market is closed.
+    pub fn next_close(&self, utc:NaiveDateTime) -> NaiveDateTime {
+        self.current_or_next_session(utc).1
+    }
+
+    /// Resolves immediately if the market is open, otherwise at the next open.
+    pub async fn await_market_open(&self) {
+        sleep_until(self.next_open(now())).await;
+    }
+
+    /// Resolves at the end of the current session, or of the next one if the market is closed.
+    pub async fn await_market_close(&self) {
+        sleep_until(self.next_close(now())).await;
+    }
+
+    /// Waits for the market to open then runs task until it completes or the session closes, whichever is first.
+    /// Returns None if the task was cut off by the close. Call it in a loop to run every session.
+    pub async fn run_during_market_hours<F:Future>(&self, task:F) -> Option<F::Output> {
+        self.await_market_open().await;
+        let close = self.next_close(now());
+        tokio::select! {
+            output = task => Some(output),
+            _ = sleep_until(close) => None,
+        }
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+/// Sleeps in steps of at most [`MAX_SLEEP`], checking the wall clock after each, since tokio's timer doesn't
+/// advance while the machine is suspended.
+async fn sleep_until(utc:NaiveDateTime) {
+    while let Ok(wait) = (utc - now()).to_std() {
+        if wait.is_zero() {
+            break;
+        }
+        tokio::time::sleep(wait.min(MAX_SLEEP)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::MockTransport;
+    use reqwest::StatusCode;
+
+    fn utc(s:&str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_schedule() {
+        let july4 = NaiveDate::from_ymd_opt(2024, 7, 4).unwrap();
+        let july3 = NaiveDate::from_ymd_opt(2024, 7, 3).unwrap();
+        let schedule = MarketSchedule::new().holiday(july4).early_close(july3, NaiveTime::from_hms_opt(13, 0, 0).unwrap());
+
+        assert!(schedule.is_open(utc("2024-07-03 16:59")));
+        assert!(!schedule.is_open(utc("2024-07-03 17:00")));
+        assert!(!schedule.is_open(utc("2024-07-04 15:00")));
+        assert_eq!(schedule.next_close(utc("2024-07-03 14:00")), utc("2024-07-03 17:00"));
+        // After the early close the next session skips the holiday.
+        assert_eq!(schedule.next_open(utc("2024-07-03 18:00")), utc("2024-07-05 13:30"));
+        assert_eq!(schedule.next_close(utc("2024-07-03 18:00")), utc("2024-07-05 20:00"));
+        // Friday evening to Monday, in winter time.
+        assert_eq!(schedule.next_open(utc("2024-01-05 21:00")), utc("2024-01-08 14:30"));
+        assert_eq!(schedule.next_open(utc("2024-01-08 15:00")), utc("2024-01-08 15:00"));
+    }
+
+    #[tokio::test]
+    async fn test_load_calendar() {
+        let body = r#"{"calendar":{"month":7,"year":2024,"days":{"day":[
+            {"date":"2024-07-03","status":"open","open":{"start":"09:30","end":"13:00"}},
+            {"date":"2024-07-04","status":"closed","description":"Market is closed for Independence Day"},
+            {"date":"2024-07-05","status":"open","open":{"start":"09:30","end":"16:00"}},
+            {"date":"2024-07-06","status":"closed","description":"Market is closed"}]}}}"#;
+        let transport = MockTransport::new().with_response("/markets/calendar", StatusCode::OK, body);
+        let schedule = MarketSchedule::load(&transport, &[(2024, 7)]).await.unwrap();
+        assert_eq!(transport.requests()[0].uri, "/markets/calendar?month=07&year=2024");
+        assert_eq!(schedule.holidays, HashSet::from([NaiveDate::from_ymd_opt(2024, 7, 4).unwrap()]));
+        assert_eq!(schedule.next_close(utc("2024-07-03 14:00")), utc("2024-07-03 17:00"));
+        assert_eq!(schedule.next_open(utc("2024-07-03 18:00")), utc("2024-07-05 13:30"));
+
+        assert!(matches!(MarketSchedule::new().with_calendar(r#"{"calendar":null}"#), Err(Error::Parse(_))));
+        let missing = MockTransport::new();
+        assert!(matches!(MarketSchedule::load(&missing, &[(2024, 7)]).await, Err(Error::Status { .. })));
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use crate::{data::Handler, events::StreamEvent, watchdog::FeedHealth};
+
+/// Tracks the last timesale sequence number per symbol.
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    last: HashMap<String, u64>,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records seq for the symbol and returns a gap if it isn't one more than the previous.
+    /// The first seq seen for a symbol, and seq 0 which Tradier sends when it has none, are never gaps.
+    pub fn record(&mut self, symbol:&str, seq:u64) -> Option<FeedHealth> {
+        if seq == 0 {
+            return None;
+        }
+        let prev = self.last.insert(symbol.to_string(), seq)?;
+        let expected = prev + 1;
+        (seq != expected).then(|| FeedHealth::SequenceGap { symbol: symbol.to_string(), expected, received: seq })
+    }
+
+    /// Forgets all symbols, eg. after deliberately resubscribing.
+    pub fn reset(&mut self) {
+        self.last.clear();
+    }
+}
+
+/// Wraps a typed handler, passing everything through and reporting timesale sequence gaps to
+/// [`Handler::on_feed_health`] as [`FeedHealth::SequenceGap`], before the event that revealed the gap.
+/// Sequence numbers carry on across reconnects, so messages missed while reconnecting show up as a gap too.
+/// A handler keeping a book can respond by refreshing the symbol from a REST quote.
+pub struct GapDetector<H> {
+    inner: H,
+    tracker: SequenceTracker,
+}
+
+impl<H> GapDetector<H> {
+    pub fn new(inner:H) -> Self {
+        Self { inner, tracker: SequenceTracker::new() }
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<H:Handler<StreamEvent>> Handler<StreamEvent> for GapDetector<H> {
+    fn on_data(&mut self, timestamp:DateTime<Utc>, data:StreamEvent) {
+        if let StreamEvent::Timesale(t) = &data {
+            if let Some(gap) = self.tracker.record(&t.symbol, t.seq) {
+                self.inner.on_feed_health(gap);
+            }
+        }
+        self.inner.on_data(timestamp, data)
+    }
+
+    fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    fn on_feed_health(&mut self, health:FeedHealth) {
+        self.inner.on_feed_health(health)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::parse_event;
+
+    #[derive(Default)]
+    struct Collect {
+        seqs: Vec<u64>,
+        health: Vec<FeedHealth>,
+    }
+
+    impl Handler<StreamEvent> for Collect {
+        fn on_data(&mut self, _timestamp:DateTime<Utc>, data:StreamEvent) {
+            if let StreamEvent::Timesale(t) = data {
+                self.seqs.push(t.seq);
+            }
+        }
+
+        fn on_feed_health(&mut self, health:FeedHealth) {
+            self.health.push(health);
+        }
+    }
+
+    fn timesale(symbol:&str, seq:u64) -> StreamEvent {
+        parse_event(&format!(r#"{{"type":"timesale","symbol":"{}","bid":"1","ask":"1","last":"1","size":"1","date":"0","seq":{}}}"#, symbol, seq)).unwrap()
+    }
+
+    #[test]
+    fn test_gap_detector() {
+        let mut detector = GapDetector::new(Collect::default());
+        for (symbol, seq) in [("SPY", 5), ("SPY", 6), ("QQQ", 100), ("SPY", 9), ("SPY", 8), ("QQQ", 101), ("QQQ", 0)] {
+            detector.on_data(DateTime::<Utc>::default(), timesale(symbol, seq));
+        }
+        let collect = detector.into_inner();
+        assert_eq!(collect.seqs, [5, 6, 100, 9, 8, 101, 0]);
+        assert_eq!(collect.health, [
+            FeedHealth::SequenceGap { symbol: "SPY".to_string(), expected: 7, received: 9 },
+            FeedHealth::SequenceGap { symbol: "SPY".to_string(), expected: 10, received: 8 },
+        ]);
+    }
+}
@@ -0,0 +1,90 @@
+//! Date formats used by Tradier, for `#[serde(with = "...")]` on types built on this crate.
+//! Unexpected shapes are deserialization errors, never panics.
+
+/// `DateTime<Utc>` as epoch milliseconds. Accepts a number or a numeric string, as the stream sends both.
+pub mod epoch_millis {
+    use chrono::{DateTime, Utc};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumOrStr {
+        Num(i64),
+        Str(String),
+    }
+
+    pub fn serialize<S:Serializer>(time:&DateTime<Utc>, s:S) -> Result<S::Ok, S::Error> {
+        s.serialize_i64(time.timestamp_millis())
+    }
+
+    pub fn deserialize<'de, D:Deserializer<'de>>(d:D) -> Result<DateTime<Utc>, D::Error> {
+        let millis = match NumOrStr::deserialize(d)? {
+            NumOrStr::Num(n) => n,
+            NumOrStr::Str(s) => s.trim().parse().map_err(de::Error::custom)?,
+        };
+        DateTime::from_timestamp_millis(millis).ok_or_else(|| de::Error::custom(format!("epoch millis out of range: {}", millis)))
+    }
+}
+
+/// `DateTime<Utc>` as an RFC 3339 string, eg. `2024-07-01T13:30:00Z`. Any offset is accepted and converted to UTC.
+pub mod rfc3339 {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S:Serializer>(time:&DateTime<Utc>, s:S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+
+    pub fn deserialize<'de, D:Deserializer<'de>>(d:D) -> Result<DateTime<Utc>, D::Error> {
+        let s = String::deserialize(d)?;
+        DateTime::parse_from_rfc3339(s.trim()).map(|t| t.with_timezone(&Utc)).map_err(de::Error::custom)
+    }
+}
+
+/// `NaiveDateTime` in Tradier's `YYYY-MM-DD HH:MM:SS` format, eg. the time field of history and timesales.
+/// Tradier gives these in US/Eastern; see [`crate::market_hours::from_eastern`] to convert.
+/// A `T` separator is also accepted on input.
+pub mod tradier_datetime {
+    use chrono::NaiveDateTime;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+    pub fn serialize<S:Serializer>(time:&NaiveDateTime, s:S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&time.format(FORMAT).to_string())
+    }
+
+    pub fn deserialize<'de, D:Deserializer<'de>>(d:D) -> Result<NaiveDateTime, D::Error> {
+        let s = String::deserialize(d)?;
+        NaiveDateTime::parse_from_str(&s.trim().replacen('T', " ", 1), FORMAT).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDateTime, Utc};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Times {
+        #[serde(with = "super::epoch_millis")]
+        millis: DateTime<Utc>,
+        #[serde(with = "super::rfc3339")]
+        rfc: DateTime<Utc>,
+        #[serde(with = "super::tradier_datetime")]
+        local: NaiveDateTime,
+    }
+
+    #[test]
+    fn test_serde_helpers() {
+        let t: Times = serde_json::from_str(r#"{"millis":"1557757190000","rfc":"2019-05-13T10:19:50-04:00","local":"2019-05-13T10:19:50"}"#).unwrap();
+        assert_eq!(t.millis, t.rfc);
+        assert_eq!(t.local, NaiveDateTime::parse_from_str("2019-05-13 10:19:50", "%Y-%m-%d %H:%M:%S").unwrap());
+        let json = serde_json::to_string(&t).unwrap();
+        assert_eq!(json, r#"{"millis":1557757190000,"rfc":"2019-05-13T14:19:50Z","local":"2019-05-13 10:19:50"}"#);
+        assert_eq!(serde_json::from_str::<Times>(&json).unwrap(), t);
+
+        assert!(serde_json::from_str::<Times>(r#"{"millis":{"$date":1},"rfc":"","local":""}"#).is_err());
+        assert!(serde_json::from_str::<Times>(r#"{"millis":"x","rfc":"2019-05-13T14:19:50Z","local":"2019-05-13 10:19:50"}"#).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use chrono::{DateTime, Utc};
+use std::{collections::HashMap, sync::{Arc, RwLock}};
+use crate::{data::Handler, events::{QuoteEvent, StreamEvent, SummaryEvent, TimesaleEvent, TradeEvent}};
+
+/// Latest known market state for one symbol.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolState {
+    pub last_trade: Option<TradeEvent>,
+    /// Best bid and offer.
+    pub quote: Option<QuoteEvent>,
+    pub summary: Option<SummaryEvent>,
+    pub last_timesale: Option<TimesaleEvent>,
+    /// Receive time of the last event for this symbol.
+    pub updated: Option<DateTime<Utc>>,
+}
+
+/// Consumes typed stream events and keeps the latest state per symbol.
+/// Pass it (wrapped in a [`crate::events::TypedHandler`]) to the stream and keep a [`MarketState`] from [`MarketStateStore::reader`]
+/// to query the state from any thread, async or not.
+#[derive(Debug, Default)]
+pub struct MarketStateStore {
+    state: Arc<RwLock<HashMap<String, SymbolState>>>,
+}
+
+impl MarketStateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reader(&self) -> MarketState {
+        MarketState { state: self.state.clone() }
+    }
+}
+
+impl Handler<StreamEvent> for MarketStateStore {
+    fn on_data(&mut self, timestamp:DateTime<Utc>, data:StreamEvent) {
+        let Some(symbol) = data.symbol() else { return };
+        let mut state = self.state.write().unwrap();
+        let entry = match state.get_mut(symbol) {
+            Some(entry) => entry,
+            None => state.entry(symbol.to_string()).or_default(),
+        };
+        entry.updated = Some(timestamp);
+        match data {
+            StreamEvent::Quote(e) => entry.quote = Some(e),
+            StreamEvent::Trade(e) | StreamEvent::Tradex(e) => entry.last_trade = Some(e),
+            StreamEvent::Summary(e) => entry.summary = Some(e),
+            StreamEvent::Timesale(e) => entry.last_timesale = Some(e),
+            StreamEvent::Other => (),
+        }
+    }
+}
+
+/// Read handle to a [`MarketStateStore`]. Cheap to clone. Reads return snapshot copies so no lock is held by the caller.
+#[derive(Debug, Clone)]
+pub struct MarketState {
+    state: Arc<RwLock<HashMap<String, SymbolState>>>,
+}
+
+impl MarketState {
+    pub fn get(&self, symbol:&str) -> Option<SymbolState> {
+        self.state.read().unwrap().get(symbol).cloned()
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, SymbolState> {
+        self.state.read().unwrap().clone()
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        self.state.read().unwrap().keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::TypedHandler;
+
+    #[test]
+    fn test_store() {
+        let store = MarketStateStore::new();
+        let reader = store.reader();
+        let mut handler = TypedHandler::new(store);
+        let t1 = DateTime::<Utc>::default();
+        let t2 = t1 + chrono::Duration::seconds(1);
+
+        handler.on_data(t1, r#"{"type":"quote","symbol":"SPY","bid":500.1,"bidsz":5,"bidexch":"Q","biddate":"1","ask":500.2,"asksz":7,"askexch":"Z","askdate":"2"}"#.to_string());
+        handler.on_data(t2, r#"{"type":"trade","symbol":"SPY","exch":"Q","price":"500.15","size":"100","cvol":"1000","date":"3","last":"500.15"}"#.to_string());
+        handler.on_data(t2, r#"{"type":"summary","symbol":"QQQ","open":"400","high":"401","low":"399","prevClose":"398"}"#.to_string());
+        handler.on_data(t2, "garbage".to_string());
+
+        let spy = reader.get("SPY").unwrap();
+        assert_eq!(spy.quote.unwrap().ask, 500.2);
+        assert_eq!(spy.last_trade.unwrap().price, 500.15);
+        assert_eq!(spy.summary, None);
+        assert_eq!(spy.updated, Some(t2));
+        assert_eq!(reader.get("QQQ").unwrap().summary.unwrap().prev_close, Some(398.0));
+        assert_eq!(reader.get("IWM"), None);
+        assert_eq!(reader.snapshot().len(), 2);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{margin::{LegSide, StrategyLeg, CONTRACT_MULTIPLIER}, options::{OptionRight, OptionSpec}};
+
+/// An option contract with its current bid and ask, eg. one row of an option chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionQuote {
+    pub spec: OptionSpec,
+    pub bid: f64,
+    pub ask: f64,
+}
+
+impl OptionQuote {
+    pub fn new(spec:OptionSpec, bid:f64, ask:f64) -> Self {
+        Self { spec, bid, ask }
+    }
+
+    /// See [`mid_price`].
+    pub fn mid(&self) -> Option<f64> {
+        mid_price(self.bid, self.ask)
+    }
+
+    pub fn spread(&self) -> f64 {
+        self.ask - self.bid
+    }
+}
+
+/// Midpoint of a two sided market. None if either side has no price (0 or less): a far out of the money option
+/// with no bid is worth less than half its ask, so halving it would overstate the value.
+pub fn mid_price(bid:f64, ask:f64) -> Option<f64> {
+    (bid > 0.0 && ask > 0.0).then(|| (bid + ask) / 2.0)
+}
+
+/// A multi-leg position priced at the natural price: long legs pay the ask, short legs receive the bid.
+/// Money amounts are dollars for the whole position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strategy {
+    /// Usable with [`crate::margin::estimate_margin`] and for building orders.
+    pub legs: Vec<StrategyLeg>,
+    /// Positive for a credit, negative for a debit.
+    pub net_premium: f64,
+    /// None when unlimited, or when not defined at a single expiration (calendars).
+    pub max_profit: Option<f64>,
+    /// As a positive amount. None when unlimited, or when not defined at a single expiration (calendars).
+    pub max_loss: Option<f64>,
+    /// Underlying prices at expiration where the position breaks even, ascending. Empty for calendars.
+    pub breakevens: Vec<f64>,
+}
+
+impl Strategy {
+    /// Builds a strategy from arbitrary legs. Legs with different expirations get no expiration payoff analysis.
+    pub fn from_legs(legs:Vec<StrategyLeg>) -> Self {
+        let net_premium = legs.iter().map(|leg| match leg.side {
+            LegSide::Long => -leg.premium,
+            LegSide::Short => leg.premium,
+        } * CONTRACT_MULTIPLIER * leg.quantity as f64).sum();
+        let single_expiration = legs.windows(2).all(|w| w[0].spec.expiration == w[1].spec.expiration);
+        let (max_profit, max_loss, breakevens) = if single_expiration && !legs.is_empty() {
+            analyze_expiration(&legs)
+        } else {
+            (None, None, Vec::new())
+        };
+        Self { legs, net_premium, max_profit, max_loss, breakevens }
+    }
+
+    /// Profit or loss for the whole position if held to expiration with the underlying at price.
+    pub fn payoff_at(&self, price:f64) -> f64 {
+        payoff(&self.legs, price)
+    }
+}
+
+/// Buy one strike and sell another of the same type and expiration.
+pub fn vertical(long:&OptionQuote, short:&OptionQuote, quantity:u32) -> Option<Strategy> {
+    if long.spec.right != short.spec.right || long.spec.expiration != short.spec.expiration || long.spec.strike == short.spec.strike {
+        return None;
+    }
+    build(&[(long, LegSide::Long), (short, LegSide::Short)], quantity)
+}
+
+/// Buy (Long) or sell (Short) a call and put at the same strike and expiration.
+pub fn straddle(call:&OptionQuote, put:&OptionQuote, side:LegSide, quantity:u32) -> Option<Strategy> {
+    if call.spec.strike != put.spec.strike {
+        return None;
+    }
+    strangle(call, put, side, quantity)
+}
+
+/// Buy (Long) or sell (Short) a call and a put with the same expiration, usually the call strike above the put.
+pub fn strangle(call:&OptionQuote, put:&OptionQuote, side:LegSide, quantity:u32) -> Option<Strategy> {
+    if call.spec.right != OptionRight::Call || put.spec.right != OptionRight::Put || call.spec.expiration != put.spec.expiration {
+        return None;
+    }
+    build(&[(call, side), (put, side)], quantity)
+}
+
+/// Short put spread plus short call spread, strikes ascending: long put, short put, short call, long call.
+pub fn iron_condor(long_put:&OptionQuote, short_put:&OptionQuote, short_call:&OptionQuote, long_call:&OptionQuote, quantity:u32) -> Option<Strategy> {
+    let strikes = [long_put.spec.strike, short_put.spec.strike, short_call.spec.strike, long_call.spec.strike];
+    let ordered = strikes.windows(2).all(|w| w[0] <= w[1]) && strikes[0] < strikes[1] && strikes[2] < strikes[3];
+    let rights = [long_put, short_put].iter().all(|q| q.spec.right == OptionRight::Put)
+        && [short_call, long_call].iter().all(|q| q.spec.right == OptionRight::Call);
+    let same_expiration = [short_put, short_call, long_call].iter().all(|q| q.spec.expiration == long_put.spec.expiration);
+    if !ordered || !rights || !same_expiration {
+        return None;
+    }
+    build(&[(long_put, LegSide::Long), (short_put, LegSide::Short), (short_call, LegSide::Short), (long_call, LegSide::Long)], quantity)
+}
+
+/// Sell the near expiration and buy the far one at the same strike and type.
+pub fn calendar(near:&OptionQuote, far:&OptionQuote, quantity:u32) -> Option<Strategy> {
+    if near.spec.right != far.spec.right || near.spec.strike != far.spec.strike || near.spec.expiration >= far.spec.expiration {
+        return None;
+    }
+    build(&[(near, LegSide::Short), (far, LegSide::Long)], quantity)
+}
+
+fn build(legs:&[(&OptionQuote, LegSide)], quantity:u32) -> Option<Strategy> {
+    if quantity == 0 || legs.iter().any(|(q, _)| q.spec.underlying != legs[0].0.spec.underlying) {
+        return None;
+    }
+    Some(Strategy::from_legs(legs.iter().map(|(q, side)| {
+        let premium = match side {
+            LegSide::Long => q.ask,
+            LegSide::Short => q.bid,
+        };
+        StrategyLeg::new(q.spec.clone(), *side, quantity, premium)
+    }).collect()))
+}
+
+fn payoff(legs:&[StrategyLeg], price:f64) -> f64 {
+    legs.iter().map(|leg| {
+        let intrinsic = match leg.spec.right {
+            OptionRight::Call => (price - leg.spec.strike).max(0.0),
+            OptionRight::Put => (leg.spec.strike - price).max(0.0),
+        };
+        let per_share = match leg.side {
+            LegSide::Long => intrinsic - leg.premium,
+            LegSide::Short => leg.premium - intrinsic,
+        };
+        per_share * CONTRACT_MULTIPLIER * leg.quantity as f64
+    }).sum()
+}
+
+/// The expiration payoff is piecewise linear with kinks at the strikes, so checking the strikes, zero,
+/// and the slope above the highest strike is enough.
+fn analyze_expiration(legs:&[StrategyLeg]) -> (Option<f64>, Option<f64>, Vec<f64>) {
+    let mut points = vec![0.0];
+    points.extend(legs.iter().map(|leg| leg.spec.strike));
+    points.sort_by(f64::total_cmp);
+    points.dedup();
+    let values = points.iter().map(|&p| payoff(legs, p)).collect::<Vec<_>>();
+    let slope = legs.iter().filter(|leg| leg.spec.right == OptionRight::Call).map(|leg| match leg.side {
+        LegSide::Long => 1.0,
+        LegSide::Short => -1.0,
+    } * CONTRACT_MULTIPLIER * leg.quantity as f64).sum::<f64>();
+
+    let max = values.iter().copied().fold(f64::MIN, f64::max);
+    let min = values.iter().copied().fold(f64::MAX, f64::min);
+    let max_profit = (slope <= 0.0).then_some(max);
+    let max_loss = (slope >= 0.0).then_some(-min.min(0.0));
+
+    let mut breakevens = Vec::new();
+    for i in 0..points.len() {
+        let (p, v) = (points[i], values[i]);
+        if v == 0.0 {
+            breakevens.push(p);
+        } else if let Some((&next_p, &next_v)) = points.get(i + 1).zip(values.get(i + 1)) {
+            if next_v != 0.0 && v.signum() != next_v.signum() {
+                breakevens.push(p + (next_p - p) * v / (v - next_v));
+            }
+        } else if slope != 0.0 && v.signum() != slope.signum() {
+            breakevens.push(p - v / slope);
+        }
+    }
+    (max_profit, max_loss, breakevens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn quote(right:OptionRight, strike:f64, bid:f64, ask:f64) -> OptionQuote {
+        OptionQuote::new(OptionSpec::new("SPY", NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(), right, strike), bid, ask)
+    }
+
+    fn assert_close(a:f64, b:f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_vertical() {
+        let s = vertical(&quote(OptionRight::Call, 500.0, 5.0, 5.2), &quote(OptionRight::Call, 510.0, 1.9, 2.0), 2).unwrap();
+        assert_close(s.net_premium, -660.0);
+        assert_close(s.max_profit.unwrap(), 1340.0);
+        assert_close(s.max_loss.unwrap(), 660.0);
+        assert_eq!(s.breakevens.len(), 1);
+        assert_close(s.breakevens[0], 503.3);
+        assert!(vertical(&quote(OptionRight::Call, 500.0, 5.0, 5.2), &quote(OptionRight::Put, 510.0, 1.9, 2.0), 1).is_none());
+    }
+
+    #[test]
+    fn test_mid_spread() {
+        assert_close(quote(OptionRight::Call, 500.0, 5.0, 5.2).mid().unwrap(), 5.1);
+        assert_close(quote(OptionRight::Call, 500.0, 5.0, 5.2).spread(), 0.2);
+        assert_eq!(quote(OptionRight::Call, 600.0, 0.0, 0.05).mid(), None);
+        assert_close(quote(OptionRight::Call, 600.0, 0.0, 0.05).spread(), 0.05);
+        assert_eq!(mid_price(1.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_straddle_strangle() {
+        let call = quote(OptionRight::Call, 500.0, 4.0, 4.2);
+        let put = quote(OptionRight::Put, 500.0, 3.8, 4.0);
+        let long = straddle(&call, &put, LegSide::Long, 1).unwrap();
+        assert_close(long.net_premium, -820.0);
+        assert_eq!(long.max_profit, None);
+        assert_close(long.max_loss.unwrap(), 820.0);
+        assert_eq!(long.breakevens.len(), 2);
+        assert_close(long.breakevens[0], 491.8);
+        assert_close(long.breakevens[1], 508.2);
+
+        let short = strangle(&quote(OptionRight::Call, 510.0, 1.0, 1.1), &quote(OptionRight::Put, 490.0, 1.5, 1.6), LegSide::Short, 1).unwrap();
+        assert_close(short.max_profit.unwrap(), 250.0);
+        assert_eq!(short.max_loss, None);
+        assert_close(short.breakevens[0], 487.5);
+        assert_close(short.breakevens[1], 512.5);
+        assert!(straddle(&call, &quote(OptionRight::Put, 495.0, 1.0, 1.1), LegSide::Long, 1).is_none());
+    }
+
+    #[test]
+    fn test_iron_condor_calendar() {
+        let s = iron_condor(
+            &quote(OptionRight::Put, 480.0, 0.4, 0.5), &quote(OptionRight::Put, 490.0, 1.5, 1.6),
+            &quote(OptionRight::Call, 510.0, 1.2, 1.3), &quote(OptionRight::Call, 520.0, 0.3, 0.4), 1).unwrap();
+        assert_close(s.net_premium, 180.0);
+        assert_close(s.max_profit.unwrap(), 180.0);
+        assert_close(s.max_loss.unwrap(), 820.0);
+        assert_close(s.breakevens[0], 488.2);
+        assert_close(s.breakevens[1], 511.8);
+        assert_close(s.payoff_at(500.0), 180.0);
+
+        let mut far = quote(OptionRight::Call, 500.0, 8.0, 8.2);
+        far.spec.expiration = NaiveDate::from_ymd_opt(2024, 7, 19).unwrap();
+        let c = calendar(&quote(OptionRight::Call, 500.0, 4.0, 4.2), &far, 1).unwrap();
+        assert_close(c.net_premium, -420.0);
+        assert_eq!((c.max_profit, c.max_loss), (None, None));
+        assert!(c.breakevens.is_empty());
+        assert!(calendar(&far, &quote(OptionRight::Call, 500.0, 4.0, 4.2), 1).is_none());
+    }
+}
@@ -0,0 +1,316 @@
+use chrono::{DateTime, Utc};
+use futures_util::{task::AtomicWaker, Stream};
+use std::{collections::VecDeque, mem::discriminant, pin::Pin, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, task::{Context, Poll}};
+use crate::{data::{Handler, MarketData}, events::StreamEvent};
+#[cfg(feature = "stream")]
+use crate::{data::run_async, events::TypedHandler};
+
+/// What to do with a new message when a channel is full.
+/// The websocket read loop calls handlers synchronously, so there's no option to wait for a slow consumer:
+/// that would stall the feed for everyone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryPolicy {
+    /// Never drop, the queue grows as needed.
+    Unbounded,
+    /// Drop the oldest queued message to make room.
+    DropOldest,
+    /// Drop the new message.
+    DropNewest,
+    /// Replace a queued message with the same [`Conflate`] key, so only the latest per key is kept.
+    /// If there's none and the queue is full, drop the oldest.
+    Conflate,
+}
+
+/// Decides which messages replace each other under [`DeliveryPolicy::Conflate`].
+pub trait Conflate {
+    fn conflates_with(&self, other:&Self) -> bool;
+}
+
+/// Raw messages are never conflated.
+impl Conflate for String {
+    fn conflates_with(&self, _other:&Self) -> bool {
+        false
+    }
+}
+
+/// Same event type for the same symbol.
+impl Conflate for StreamEvent {
+    fn conflates_with(&self, other:&Self) -> bool {
+        discriminant(self) == discriminant(other) && self.symbol().is_some() && self.symbol() == other.symbol()
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    waker: AtomicWaker,
+    received: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl<T> Shared<T> {
+    fn stats(&self, capacity:usize) -> ChannelStats {
+        ChannelStats {
+            received: self.received.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            queued: self.state.lock().unwrap().queue.len(),
+            capacity: (capacity != usize::MAX).then_some(capacity),
+        }
+    }
+}
+
+/// Counters for one channel, to see whether its consumer is falling behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// Messages passed to the handler, including ones later dropped.
+    pub received: u64,
+    /// Messages dropped or conflated away.
+    pub dropped: u64,
+    /// Messages waiting to be read from the stream.
+    pub queued: usize,
+    /// None for unbounded channels.
+    pub capacity: Option<usize>,
+}
+
+struct State<T> {
+    queue: VecDeque<MarketData<T>>,
+    senders: usize,
+    receiver_closed: bool,
+}
+
+/// Handler that sends everything it receives to a [`MarketDataStream`], applying the channel's [`DeliveryPolicy`]
+/// when the stream falls behind. It reports done once the stream is dropped, which ends the websocket run loop.
+pub struct ChannelHandler<T> {
+    shared: Arc<Shared<T>>,
+    capacity: usize,
+    policy: DeliveryPolicy,
+}
+
+impl<T> ChannelHandler<T> {
+    /// Number of messages dropped or conflated away so far.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        self.shared.stats(self.capacity)
+    }
+}
+
+impl<T:Conflate> Handler<T> for ChannelHandler<T> {
+    fn on_data(&mut self, timestamp:DateTime<Utc>, data:T) {
+        let msg = MarketData { timestamp, data };
+        self.shared.received.fetch_add(1, Ordering::Relaxed);
+        let mut state = self.shared.state.lock().unwrap();
+        if state.receiver_closed {
+            return;
+        }
+        let queue = &mut state.queue;
+        let full = queue.len() >= self.capacity;
+        let dropped = match self.policy {
+            DeliveryPolicy::Unbounded => { queue.push_back(msg); false },
+            DeliveryPolicy::DropNewest if full => true,
+            DeliveryPolicy::Conflate => match queue.iter_mut().find(|queued| queued.data.conflates_with(&msg.data)) {
+                Some(queued) => { *queued = msg; true },
+                None => push_dropping_oldest(queue, msg, full),
+            },
+            DeliveryPolicy::DropOldest | DeliveryPolicy::DropNewest => push_dropping_oldest(queue, msg, full),
+        };
+        drop(state);
+        if dropped {
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        self.shared.waker.wake();
+    }
+
+    fn is_done(&self) -> bool {
+        self.shared.state.lock().unwrap().receiver_closed
+    }
+}
+
+fn push_dropping_oldest<T>(queue:&mut VecDeque<T>, msg:T, full:bool) -> bool {
+    if full {
+        queue.pop_front();
+    }
+    queue.push_back(msg);
+    full
+}
+
+impl<T> Clone for ChannelHandler<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Self { shared: self.shared.clone(), capacity: self.capacity, policy: self.policy }
+    }
+}
+
+impl<T> Drop for ChannelHandler<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().senders -= 1;
+        self.shared.waker.wake();
+    }
+}
+
+/// A [`Stream`] of market data, so [`futures_util::StreamExt`] combinators can be used on live data.
+/// Ends when all of its handlers are dropped.
+pub struct MarketDataStream<T> {
+    shared: Arc<Shared<T>>,
+    capacity: usize,
+}
+
+impl<T> MarketDataStream<T> {
+    /// Number of messages dropped or conflated away so far.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        self.shared.stats(self.capacity)
+    }
+}
+
+impl<T> Stream for MarketDataStream<T> {
+    type Item = MarketData<T>;
+
+    fn poll_next(self:Pin<&mut Self>, cx:&mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Register before checking so a message sent in between isn't missed.
+        self.shared.waker.register(cx.waker());
+        let mut state = self.shared.state.lock().unwrap();
+        match state.queue.pop_front() {
+            Some(msg) => Poll::Ready(Some(msg)),
+            None if state.senders == 0 => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for MarketDataStream<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receiver_closed = true;
+        state.queue.clear();
+    }
+}
+
+/// Creates a connected handler and stream that never drops messages. Pass the handler to the websocket run loop,
+/// eg. [`crate::data::run_async`], directly for raw messages or wrapped in a [`crate::events::TypedHandler`] for parsed events.
+pub fn market_data_channel<T>() -> (ChannelHandler<T>, MarketDataStream<T>) {
+    market_data_channel_with(usize::MAX, DeliveryPolicy::Unbounded)
+}
+
+/// Like [`market_data_channel`] but holds at most capacity messages, applying policy when full.
+pub fn market_data_channel_with<T>(capacity:usize, policy:DeliveryPolicy) -> (ChannelHandler<T>, MarketDataStream<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State { queue: VecDeque::new(), senders: 1, receiver_closed: false }),
+        waker: AtomicWaker::new(),
+        received: AtomicU64::new(0),
+        dropped: AtomicU64::new(0),
+    });
+    let capacity = capacity.max(1);
+    (ChannelHandler { shared: shared.clone(), capacity, policy }, MarketDataStream { shared, capacity })
+}
+
+/// Subscribes to the symbols on a spawned task and returns the parsed events as a stream.
+/// Dropping the stream closes the connection after the next message arrives.
+/// Must be called from within a tokio runtime.
+#[cfg(feature = "stream")]
+pub fn stream_symbols(symbols:&[&str]) -> impl Stream<Item = MarketData<StreamEvent>> {
+    stream_symbols_with(symbols, usize::MAX, DeliveryPolicy::Unbounded)
+}
+
+/// Like [`stream_symbols`] with the given capacity and [`DeliveryPolicy`].
+#[cfg(feature = "stream")]
+pub fn stream_symbols_with(symbols:&[&str], capacity:usize, policy:DeliveryPolicy) -> impl Stream<Item = MarketData<StreamEvent>> {
+    let (handler, stream) = market_data_channel_with(capacity, policy);
+    let symbols = symbols.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    tokio::spawn(async move {
+        let symbols = symbols.iter().map(String::as_str).collect::<Vec<_>>();
+        run_async(TypedHandler::new(handler), &symbols).await;
+    });
+    stream
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::TypedHandler;
+    use futures_util::StreamExt;
+
+    fn summary(symbol:&str, open:u32) -> String {
+        format!(r#"{{"type":"summary","symbol":"{}","open":"{}"}}"#, symbol, open)
+    }
+
+    async fn collect(handler:TypedHandler<ChannelHandler<StreamEvent>>, stream:MarketDataStream<StreamEvent>) -> Vec<(String, f64)> {
+        drop(handler);
+        stream.filter_map(|md| async move {
+            match md.data {
+                StreamEvent::Summary(s) => Some((s.symbol, s.open.unwrap())),
+                _ => None,
+            }
+        }).collect().await
+    }
+
+    fn send_all(handler:&mut TypedHandler<ChannelHandler<StreamEvent>>, msgs:&[(&str, u32)]) {
+        for (symbol, open) in msgs {
+            handler.on_data(DateTime::<Utc>::default(), summary(symbol, *open));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_channel_stream() {
+        let (handler, stream) = market_data_channel();
+        let mut typed = TypedHandler::new(handler);
+        typed.on_data(DateTime::<Utc>::default(), summary("SPY", 500));
+        typed.on_data(DateTime::<Utc>::default(), "garbage".to_string());
+        typed.on_data(DateTime::<Utc>::default(), summary("QQQ", 400));
+        assert!(!typed.is_done());
+        assert_eq!(stream.stats(), ChannelStats { received: 2, dropped: 0, queued: 2, capacity: None });
+        assert_eq!(collect(typed, stream).await, [("SPY".to_string(), 500.0), ("QQQ".to_string(), 400.0)]);
+    }
+
+    #[test]
+    fn test_done_when_dropped() {
+        let (handler, stream) = market_data_channel::<String>();
+        assert!(!handler.is_done());
+        drop(stream);
+        assert!(handler.is_done());
+    }
+
+    #[tokio::test]
+    async fn test_drop_policies() {
+        let msgs = [("A", 1), ("B", 2), ("A", 3)];
+
+        let (handler, stream) = market_data_channel_with(2, DeliveryPolicy::DropNewest);
+        let mut typed = TypedHandler::new(handler);
+        send_all(&mut typed, &msgs);
+        assert_eq!(stream.dropped(), 1);
+        assert_eq!(stream.stats(), ChannelStats { received: 3, dropped: 1, queued: 2, capacity: Some(2) });
+        assert_eq!(collect(typed, stream).await, [("A".to_string(), 1.0), ("B".to_string(), 2.0)]);
+
+        let (handler, stream) = market_data_channel_with(2, DeliveryPolicy::DropOldest);
+        let mut typed = TypedHandler::new(handler);
+        send_all(&mut typed, &msgs);
+        assert_eq!(collect(typed, stream).await, [("B".to_string(), 2.0), ("A".to_string(), 3.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_conflate() {
+        let (handler, stream) = market_data_channel_with(2, DeliveryPolicy::Conflate);
+        let mut typed = TypedHandler::new(handler);
+        send_all(&mut typed, &[("A", 1), ("B", 2), ("A", 3), ("A", 4)]);
+        assert_eq!(stream.dropped(), 2);
+        assert_eq!(collect(typed, stream).await, [("A".to_string(), 4.0), ("B".to_string(), 2.0)]);
+
+        let (handler, stream) = market_data_channel_with(2, DeliveryPolicy::Conflate);
+        let mut typed = TypedHandler::new(handler);
+        send_all(&mut typed, &[("A", 1), ("B", 2), ("C", 3)]);
+        assert_eq!(collect(typed, stream).await, [("B".to_string(), 2.0), ("C".to_string(), 3.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_wakes_pending_stream() {
+        let (mut handler, mut stream) = market_data_channel::<String>();
+        let reader = tokio::spawn(async move { stream.next().await.map(|md| md.data) });
+        tokio::task::yield_now().await;
+        handler.on_data(DateTime::<Utc>::default(), "hello".to_string());
+        assert_eq!(reader.await.unwrap().as_deref(), Some("hello"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::{fmt, ops::Deref, str::FromStr};
+use crate::{error::Error, options::{parse_occ_option_symbol, OptionSpec}};
+
+/// Longest equity or index symbol accepted. OCC option symbols are checked by [`parse_occ_option_symbol`].
+pub const MAX_SYMBOL_LEN: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SymbolKind {
+    Equity,
+    /// Given with a `$` or `^` prefix, eg. `$SPX`. Tradier takes index symbols without the prefix.
+    Index,
+    Option,
+}
+
+/// A validated symbol in the form Tradier expects: trimmed, uppercase, index prefix removed,
+/// and options in the compact OCC form, eg. `SPY240419C00500000`.
+/// Parsing catches typos locally instead of getting an empty response from the API.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol {
+    symbol: String,
+    kind: SymbolKind,
+}
+
+impl Symbol {
+    pub fn parse(input:&str) -> Result<Self, Error> {
+        let trimmed = input.trim();
+        let invalid = |reason:&str| Error::InvalidSymbol(format!("{:?}: {}", input, reason));
+        if trimmed.is_empty() {
+            return Err(invalid("empty"));
+        }
+        if let Some(index) = trimmed.strip_prefix(['$', '^']) {
+            let symbol = normalize_plain(index).map_err(invalid)?;
+            return Ok(Self { symbol, kind: SymbolKind::Index });
+        }
+        if trimmed.len() > MAX_SYMBOL_LEN && trimmed.chars().any(|c| c.is_ascii_digit()) {
+            let spec = parse_occ_option_symbol(trimmed).ok_or_else(|| invalid("not a valid OCC option symbol"))?;
+            return Self::option(&spec).ok_or_else(|| invalid("option can't be represented as an OCC symbol"));
+        }
+        let symbol = normalize_plain(trimmed).map_err(invalid)?;
+        Ok(Self { symbol, kind: SymbolKind::Equity })
+    }
+
+    /// Parses all symbols, failing on the first invalid one.
+    pub fn parse_all(inputs:&[&str]) -> Result<Vec<Self>, Error> {
+        inputs.iter().map(|s| Self::parse(s)).collect()
+    }
+
+    pub fn option(spec:&OptionSpec) -> Option<Self> {
+        let padded = spec.to_occ_symbol()?;
+        Some(Self { symbol: padded.replace(' ', ""), kind: SymbolKind::Option })
+    }
+
+    pub fn kind(&self) -> SymbolKind {
+        self.kind
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.symbol
+    }
+
+    /// The contract details for an option symbol, None for anything else.
+    pub fn option_spec(&self) -> Option<OptionSpec> {
+        match self.kind {
+            SymbolKind::Option => parse_occ_option_symbol(&self.symbol),
+            SymbolKind::Equity | SymbolKind::Index => None,
+        }
+    }
+}
+
+/// Letters and digits with `.` or `/` allowed for share classes, eg. `BRK.B`.
+fn normalize_plain(symbol:&str) -> Result<String, &'static str> {
+    if symbol.is_empty() {
+        return Err("empty");
+    }
+    if symbol.len() > MAX_SYMBOL_LEN {
+        return Err("too long");
+    }
+    if !symbol.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '/') {
+        return Err("unexpected character");
+    }
+    if !symbol.starts_with(|c:char| c.is_ascii_alphabetic()) {
+        return Err("must start with a letter");
+    }
+    Ok(symbol.to_ascii_uppercase())
+}
+
+impl FromStr for Symbol {
+    type Err = Error;
+
+    fn from_str(s:&str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.symbol)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.symbol
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        &self.symbol
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::OptionRight;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_parse_symbol() {
+        let s = Symbol::parse(" spy ").unwrap();
+        assert_eq!((s.as_str(), s.kind()), ("SPY", SymbolKind::Equity));
+        assert_eq!(Symbol::parse("brk.b").unwrap().as_str(), "BRK.B");
+        let s = Symbol::parse("$spx").unwrap();
+        assert_eq!((s.as_str(), s.kind()), ("SPX", SymbolKind::Index));
+        assert_eq!(Symbol::parse("^VIX").unwrap().as_str(), "VIX");
+
+        for bad in ["", "  ", "$", "SP Y", "1SPY", "SPY!", "TOOLONGSYMBOL", "SPY240419X00500000"] {
+            assert!(matches!(Symbol::parse(bad), Err(Error::InvalidSymbol(_))), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_option_symbol() {
+        let spec = OptionSpec::new("SPY", NaiveDate::from_ymd_opt(2024, 4, 19).unwrap(), OptionRight::Call, 500.0);
+        for input in ["spy240419c00500000", "SPY   240419C00500000"] {
+            let s = Symbol::parse(input).unwrap();
+            assert_eq!((s.as_str(), s.kind()), ("SPY240419C00500000", SymbolKind::Option));
+            assert_eq!(s.option_spec(), Some(spec.clone()));
+        }
+        assert_eq!(Symbol::option(&spec).unwrap(), Symbol::parse("SPY240419C00500000").unwrap());
+        assert_eq!(Symbol::parse_all(&["SPY", "qqq"]).unwrap().iter().map(|s| s.to_string()).collect::<Vec<_>>(), ["SPY", "QQQ"]);
+        assert!(Symbol::parse_all(&["SPY", "?"]).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Test helpers for running the streaming client without credentials or network access.
+
+use futures_util::{SinkExt, StreamExt};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::{io, net::SocketAddr, sync::{Arc, Mutex}};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
+use crate::{data::StreamConfig, http::MockTransport};
+
+/// Session id returned by [`FakeStreamServer::transport`].
+pub const FAKE_SESSION_ID: &str = "fake-session";
+
+/// A websocket server on localhost that behaves like the Tradier stream: each connection waits for the
+/// subscription payload, records it, sends the scripted frames in order, then stays open until the client closes.
+/// Connect to it with [`FakeStreamServer::config`]. The server stops when dropped.
+pub struct FakeStreamServer {
+    addr: SocketAddr,
+    subscriptions: Arc<Mutex<Vec<Value>>>,
+    task: JoinHandle<()>,
+}
+
+impl FakeStreamServer {
+    /// Binds to a free port. Must be called from within a tokio runtime.
+    pub async fn start(frames:Vec<String>) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let subscriptions = Arc::new(Mutex::new(Vec::new()));
+        let frames = Arc::new(frames);
+        let recorded = subscriptions.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, frames.clone(), recorded.clone()));
+            }
+        });
+        Ok(Self { addr, subscriptions, task })
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    /// A transport answering the session request, as Tradier would, with [`FAKE_SESSION_ID`].
+    pub fn transport(&self) -> Arc<MockTransport> {
+        let session = json!({ "stream": { "url": self.url(), "sessionid": FAKE_SESSION_ID } }).to_string();
+        Arc::new(MockTransport::new().with_response("/markets/events/session", StatusCode::OK, &session))
+    }
+
+    /// Stream config that creates sessions with [`FakeStreamServer::transport`] and connects to this server.
+    pub fn config(&self) -> StreamConfig {
+        let mut config = StreamConfig::default().transport(self.transport());
+        config.url = self.url();
+        config
+    }
+
+    /// Subscription payloads received so far, one per connection.
+    pub fn subscriptions(&self) -> Vec<Value> {
+        self.subscriptions.lock().unwrap().clone()
+    }
+}
+
+impl Drop for FakeStreamServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(stream:tokio::net::TcpStream, frames:Arc<Vec<String>>, subscriptions:Arc<Mutex<Vec<Value>>>) {
+    let Ok(mut ws) = accept_async(stream).await else { return };
+    match ws.next().await {
+        Some(Ok(Message::Text(payload))) => {
+            subscriptions.lock().unwrap().push(serde_json::from_str(&payload).unwrap_or(Value::String(payload)));
+        },
+        _ => return,
+    }
+    for frame in frames.iter() {
+        if ws.send(Message::Text(frame.clone())).await.is_err() {
+            return;
+        }
+    }
+    while let Some(Ok(msg)) = ws.next().await {
+        if msg.is_close() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use crate::{data::{run_async_with, ConnectionEvent, Handler}, http::HttpResponse};
+
+    struct Collect {
+        msgs: Arc<Mutex<Vec<String>>>,
+        until: usize,
+    }
+
+    impl Handler<String> for Collect {
+        fn on_data(&mut self, _timestamp:DateTime<Utc>, data:String) {
+            self.msgs.lock().unwrap().push(data);
+        }
+
+        fn is_done(&self) -> bool {
+            self.msgs.lock().unwrap().len() >= self.until
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fake_stream_server() {
+        let server = FakeStreamServer::start(vec![
+            r#"{"type":"trade","symbol":"SPY","price":"500.1"}"#.to_string(),
+            "{\"type\":\"quote\",\"symbol\":\"SPY\"}\n{\"type\":\"quote\",\"symbol\":\"QQQ\"}".to_string(),
+        ]).await.unwrap();
+        let msgs = Arc::new(Mutex::new(Vec::new()));
+        let mut config = server.config();
+        let mut events = config.connection_events();
+
+        run_async_with(Collect { msgs: msgs.clone(), until: 3 }, &["SPY", "QQQ"], &config).await;
+
+        assert_eq!(msgs.lock().unwrap().len(), 3);
+        assert_eq!(msgs.lock().unwrap()[2], r#"{"type":"quote","symbol":"QQQ"}"#);
+        assert_eq!(server.subscriptions(), [json!({ "symbols": ["SPY", "QQQ"], "sessionid": FAKE_SESSION_ID, "linebreak": true })]);
+        assert_eq!(events.recv().await.unwrap(), ConnectionEvent::Connecting);
+        assert_eq!(events.recv().await.unwrap(), ConnectionEvent::Connected);
+        assert!(matches!(events.recv().await.unwrap(), ConnectionEvent::Subscribed { .. }));
+        assert_eq!(events.recv().await.unwrap(), ConnectionEvent::Disconnected { reason: "Handler is done".to_string() });
+    }
+
+    #[tokio::test]
+    async fn test_done_after_snapshots() {
+        let server = FakeStreamServer::start(Vec::new()).await.unwrap();
+        let transport = server.transport();
+        let quotes = r#"{"quotes":{"quote":[{"symbol":"BRK/B","bid":1.0,"ask":1.1},{"symbol":"SPY","bid":2.0,"ask":2.1}]}}"#;
+        transport.add_response("/markets/quotes", HttpResponse::new(StatusCode::OK, quotes));
+        let mut config = StreamConfig::default().transport(transport.clone());
+        config.url = server.url();
+        config.snapshot_on_subscribe = true;
+        let msgs = Arc::new(Mutex::new(Vec::new()));
+
+        // The server sends nothing, so this only returns if the handler is checked after the snapshots.
+        let run = run_async_with(Collect { msgs: msgs.clone(), until: 1 }, &["BRK/B", "SPY"], &config);
+        tokio::time::timeout(std::time::Duration::from_secs(5), run).await.unwrap();
+        assert_eq!(msgs.lock().unwrap().len(), 1);
+        assert_eq!(transport.requests()[1].uri, "/markets/quotes?symbols=BRK%2FB%2CSPY");
+    }
+}
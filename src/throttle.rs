@@ -0,0 +1,134 @@
+use std::{collections::HashMap, sync::{Mutex, OnceLock}, time::{Duration, Instant}};
+
+/// Default interval between reports of a repeating warning.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Rate limits repeated warnings. The first occurrence of a key is reported immediately,
+/// later occurrences are counted and reported as a summary at most once per interval.
+/// A count left over when a key stops repeating is reported by the next [`WarnThrottle::warn`] after its interval,
+/// or when the throttle is dropped, and keys idle for an interval are forgotten.
+#[derive(Debug)]
+pub struct WarnThrottle {
+    interval: Duration,
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    last_report: Instant,
+    suppressed: u64,
+    msg: String,
+}
+
+impl Entry {
+    fn summary(&self, now:Instant) -> String {
+        format!("{} (repeated {} more times in the last {:?})", self.msg, self.suppressed, now.duration_since(self.last_report))
+    }
+}
+
+impl WarnThrottle {
+    pub fn new(interval:Duration) -> Self {
+        Self { interval, entries: HashMap::new() }
+    }
+
+    /// Prints the warning if it's due, and any summaries due from [`WarnThrottle::flush`].
+    pub fn warn(&mut self, key:&str, msg:&str) {
+        let now = Instant::now();
+        if let Some(text) = self.check(key, msg, now) {
+            println!("{}", text);
+        }
+        for text in self.flush(now) {
+            println!("{}", text);
+        }
+    }
+
+    /// Records an occurrence of key at the time now, returning the text to report if one is due.
+    pub fn check(&mut self, key:&str, msg:&str, now:Instant) -> Option<String> {
+        match self.entries.get_mut(key) {
+            None => {
+                self.entries.insert(key.to_string(), Entry { last_report: now, suppressed: 0, msg: msg.to_string() });
+                Some(msg.to_string())
+            },
+            Some(entry) if now.duration_since(entry.last_report) >= self.interval => {
+                entry.msg = msg.to_string();
+                let text = if entry.suppressed == 0 { entry.msg.clone() } else { entry.summary(now) };
+                entry.last_report = now;
+                entry.suppressed = 0;
+                Some(text)
+            },
+            Some(entry) => {
+                entry.suppressed += 1;
+                if entry.msg != msg {
+                    entry.msg = msg.to_string();
+                }
+                None
+            }
+        }
+    }
+
+    /// Removes the keys not reported for an interval, returning summaries for those with suppressed occurrences.
+    pub fn flush(&mut self, now:Instant) -> Vec<String> {
+        let mut texts = Vec::new();
+        self.entries.retain(|_, entry| {
+            if now.duration_since(entry.last_report) < self.interval {
+                return true;
+            }
+            if entry.suppressed > 0 {
+                texts.push(entry.summary(now));
+            }
+            false
+        });
+        texts
+    }
+}
+
+impl Drop for WarnThrottle {
+    fn drop(&mut self) {
+        let now = Instant::now();
+        for entry in self.entries.values().filter(|entry| entry.suppressed > 0) {
+            println!("{}", entry.summary(now));
+        }
+    }
+}
+
+/// Prints the warning through a process wide [`WarnThrottle`] using [`DEFAULT_INTERVAL`].
+pub fn warn_throttled(key:&str, msg:&str) {
+    static THROTTLE: OnceLock<Mutex<WarnThrottle>> = OnceLock::new();
+    THROTTLE.get_or_init(|| Mutex::new(WarnThrottle::new(DEFAULT_INTERVAL))).lock().unwrap().warn(key, msg);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle() {
+        let mut t = WarnThrottle::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(t.check("parse", "bad msg", start).as_deref(), Some("bad msg"));
+        assert_eq!(t.check("parse", "bad msg", start + Duration::from_secs(1)), None);
+        assert_eq!(t.check("parse", "bad msg", start + Duration::from_secs(2)), None);
+        // Other keys are independent.
+        assert_eq!(t.check("retry", "retrying", start + Duration::from_secs(3)).as_deref(), Some("retrying"));
+        assert_eq!(t.check("parse", "bad msg", start + Duration::from_secs(10)).as_deref(),
+            Some("bad msg (repeated 2 more times in the last 10s)"));
+        assert_eq!(t.check("parse", "bad msg", start + Duration::from_secs(25)).as_deref(), Some("bad msg"));
+    }
+
+    #[test]
+    fn test_throttle_flush() {
+        let mut t = WarnThrottle::new(Duration::from_secs(10));
+        let start = Instant::now();
+        t.check("parse", "bad msg", start);
+        t.check("parse", "bad msg", start + Duration::from_secs(1));
+        t.check("retry", "retrying", start + Duration::from_secs(5));
+        assert!(t.flush(start + Duration::from_secs(9)).is_empty());
+        // The count is reported once the window has passed even though parse didn't occur again, and both keys
+        // are forgotten once idle for the interval.
+        assert_eq!(t.flush(start + Duration::from_secs(10)), ["bad msg (repeated 1 more times in the last 10s)"]);
+        assert_eq!(t.entries.len(), 1);
+        assert!(t.flush(start + Duration::from_secs(15)).is_empty());
+        assert!(t.entries.is_empty());
+        assert_eq!(t.check("parse", "bad msg", start + Duration::from_secs(16)).as_deref(), Some("bad msg"));
+    }
+}
@@ -0,0 +1,131 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use std::{collections::{BTreeMap, HashMap}, sync::{Arc, RwLock}};
+use crate::{data::Handler, events::StreamEvent, market_hours::to_eastern};
+
+/// Which events to count. Subscribe to one or the other; counting both would double the volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileSource {
+    Trades,
+    /// Canceled prints are skipped.
+    Timesales,
+}
+
+/// Volume traded at each price level for one symbol during one session (Eastern calendar day).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub session: NaiveDate,
+    /// Price level and volume, ascending by price.
+    pub levels: Vec<(f64, u64)>,
+    pub total_volume: u64,
+}
+
+impl Profile {
+    /// Point of control: the price level with the most volume.
+    pub fn poc(&self) -> Option<f64> {
+        self.levels.iter().max_by_key(|(_, volume)| *volume).map(|(price, _)| *price)
+    }
+}
+
+#[derive(Debug)]
+struct SymbolProfile {
+    session: NaiveDate,
+    /// Keyed by price in ticks.
+    levels: BTreeMap<i64, u64>,
+}
+
+/// Consumes typed stream events and aggregates trade prints into volume per price level, per symbol,
+/// starting over each session. Prices are grouped into levels of tick_size, eg. 0.01 or 0.05.
+/// Query it from any thread with a [`VolumeProfileReader`] from [`VolumeProfiles::reader`].
+#[derive(Debug)]
+pub struct VolumeProfiles {
+    source: ProfileSource,
+    tick_size: f64,
+    profiles: Arc<RwLock<HashMap<String, SymbolProfile>>>,
+}
+
+impl VolumeProfiles {
+    pub fn new(source:ProfileSource, tick_size:f64) -> Self {
+        Self { source, tick_size, profiles: Default::default() }
+    }
+
+    pub fn reader(&self) -> VolumeProfileReader {
+        VolumeProfileReader { tick_size: self.tick_size, profiles: self.profiles.clone() }
+    }
+
+    fn record(&mut self, timestamp:DateTime<Utc>, symbol:&str, price:f64, size:u64) {
+        let session = to_eastern(timestamp.naive_utc()).date();
+        let ticks = (price / self.tick_size).round() as i64;
+        let mut profiles = self.profiles.write().unwrap();
+        let profile = profiles.entry(symbol.to_string()).or_insert_with(|| SymbolProfile { session, levels: BTreeMap::new() });
+        if profile.session != session {
+            profile.session = session;
+            profile.levels.clear();
+        }
+        *profile.levels.entry(ticks).or_default() += size;
+    }
+}
+
+impl Handler<StreamEvent> for VolumeProfiles {
+    fn on_data(&mut self, timestamp:DateTime<Utc>, data:StreamEvent) {
+        match (self.source, data) {
+            (ProfileSource::Trades, StreamEvent::Trade(t) | StreamEvent::Tradex(t)) => self.record(timestamp, &t.symbol, t.price, t.size),
+            (ProfileSource::Timesales, StreamEvent::Timesale(t)) if !t.cancel => self.record(timestamp, &t.symbol, t.last, t.size),
+            _ => (),
+        }
+    }
+}
+
+/// Read handle to [`VolumeProfiles`]. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct VolumeProfileReader {
+    tick_size: f64,
+    profiles: Arc<RwLock<HashMap<String, SymbolProfile>>>,
+}
+
+impl VolumeProfileReader {
+    /// Snapshot of the current session's profile for the symbol.
+    pub fn profile(&self, symbol:&str) -> Option<Profile> {
+        let profiles = self.profiles.read().unwrap();
+        let profile = profiles.get(symbol)?;
+        let levels = profile.levels.iter().map(|(ticks, volume)| (*ticks as f64 * self.tick_size, *volume)).collect::<Vec<_>>();
+        Some(Profile { session: profile.session, total_volume: levels.iter().map(|(_, v)| v).sum(), levels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::parse_event;
+
+    fn trade(symbol:&str, price:f64, size:u64) -> StreamEvent {
+        parse_event(&format!(r#"{{"type":"trade","symbol":"{}","price":"{}","size":"{}","cvol":"0","date":"0","last":"{}"}}"#, symbol, price, size, price)).unwrap()
+    }
+
+    fn at(s:&str) -> DateTime<Utc> {
+        chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap().and_utc()
+    }
+
+    #[test]
+    fn test_volume_profile() {
+        let mut profiles = VolumeProfiles::new(ProfileSource::Trades, 0.05);
+        let reader = profiles.reader();
+        profiles.on_data(at("2024-07-01 14:00"), trade("SPY", 500.01, 100));
+        profiles.on_data(at("2024-07-01 14:01"), trade("SPY", 500.04, 300));
+        profiles.on_data(at("2024-07-01 14:02"), trade("SPY", 499.99, 50));
+        profiles.on_data(at("2024-07-01 14:02"), trade("QQQ", 400.0, 10));
+
+        let p = reader.profile("SPY").unwrap();
+        assert_eq!(p.session, NaiveDate::from_ymd_opt(2024, 7, 1).unwrap());
+        assert_eq!(p.total_volume, 450);
+        assert_eq!(p.levels.len(), 2);
+        assert!((p.poc().unwrap() - 500.05).abs() < 1e-9);
+
+        // 01:00 UTC on July 2 is still July 1 in New York.
+        profiles.on_data(at("2024-07-02 01:00"), trade("SPY", 500.0, 1));
+        assert_eq!(reader.profile("SPY").unwrap().total_volume, 451);
+        profiles.on_data(at("2024-07-02 14:00"), trade("SPY", 501.0, 5));
+        let p = reader.profile("SPY").unwrap();
+        assert_eq!((p.session, p.total_volume), (NaiveDate::from_ymd_opt(2024, 7, 2).unwrap(), 5));
+        assert!(reader.profile("IWM").is_none());
+    }
+}
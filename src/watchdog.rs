@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use std::{collections::BTreeMap, time::Duration};
+use crate::market_hours::is_regular_hours;
+
+/// Reported to [`crate::data::Handler::on_feed_health`] when data stops or resumes arriving.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedHealth {
+    /// No message for the symbol within the stale window. last is None if nothing was received since connecting.
+    SymbolStale { symbol: String, last: Option<DateTime<Utc>> },
+    /// Data for a symbol previously reported stale is arriving again.
+    SymbolRecovered { symbol: String },
+    /// No message on the connection at all within the stale window. The stream reconnects after this is reported.
+    ConnectionStale { last: DateTime<Utc> },
+    /// A timesale arrived with a sequence number other than the next expected one, see [`crate::sequence::GapDetector`].
+    /// received above expected means messages were missed, below means out of order or replayed.
+    SequenceGap { symbol: String, expected: u64, received: u64 },
+}
+
+/// Tracks the last message time per symbol and for the whole connection. All times are UTC.
+#[derive(Debug)]
+pub struct FeedWatchdog {
+    stale_after: chrono::Duration,
+    market_hours_only: bool,
+    connected: DateTime<Utc>,
+    last_any: DateTime<Utc>,
+    symbols: BTreeMap<String, SymbolEntry>,
+}
+
+#[derive(Debug)]
+struct SymbolEntry {
+    last: Option<DateTime<Utc>>,
+    stale: bool,
+}
+
+impl FeedWatchdog {
+    /// connected is the time the connection was made, so symbols that never receive data become stale too.
+    pub fn new(symbols:&[&str], stale_after:Duration, market_hours_only:bool, connected:DateTime<Utc>) -> Self {
+        let symbols = symbols.iter().map(|s| (s.to_string(), SymbolEntry { last: None, stale: false })).collect();
+        Self {
+            stale_after: chrono::Duration::from_std(stale_after).unwrap_or(chrono::Duration::max_value()),
+            market_hours_only,
+            connected,
+            last_any: connected,
+            symbols,
+        }
+    }
+
+    /// Records a message received at now. Returns a recovery event if the symbol had been reported stale.
+    pub fn record(&mut self, now:DateTime<Utc>, symbol:Option<&str>) -> Option<FeedHealth> {
+        self.last_any = now;
+        let entry = self.symbols.get_mut(symbol?)?;
+        entry.last = Some(now);
+        if entry.stale {
+            entry.stale = false;
+            return Some(FeedHealth::SymbolRecovered { symbol: symbol?.to_string() });
+        }
+        None
+    }
+
+    /// Returns newly stale symbols, and ConnectionStale if nothing at all arrived within the window.
+    /// Outside market hours nothing is reported when configured for market hours only.
+    pub fn check(&mut self, now:DateTime<Utc>) -> Vec<FeedHealth> {
+        let mut events = Vec::new();
+        if self.market_hours_only && !is_regular_hours(now.naive_utc()) {
+            return events;
+        }
+        let Some(cutoff) = now.checked_sub_signed(self.stale_after) else { return events };
+        for (symbol, entry) in self.symbols.iter_mut() {
+            if !entry.stale && entry.last.unwrap_or(self.connected) <= cutoff {
+                entry.stale = true;
+                events.push(FeedHealth::SymbolStale { symbol: symbol.clone(), last: entry.last });
+            }
+        }
+        if self.last_any <= cutoff {
+            events.push(FeedHealth::ConnectionStale { last: self.last_any });
+        }
+        events
+    }
+}
+
+/// Extracts the symbol from a raw stream message without fully parsing it.
+pub fn payload_symbol(payload:&str) -> Option<&str> {
+    const KEY: &str = "\"symbol\":\"";
+    let start = payload.find(KEY)? + KEY.len();
+    let len = payload[start..].find('"')?;
+    Some(&payload[start..start + len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs:i64) -> DateTime<Utc> {
+        // A Wednesday at 10:00 Eastern.
+        chrono::NaiveDateTime::parse_from_str("2024-07-03 14:00:00", "%Y-%m-%d %H:%M:%S").unwrap().and_utc() + chrono::Duration::seconds(secs)
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let mut w = FeedWatchdog::new(&["SPY", "QQQ"], Duration::from_secs(30), true, at(0));
+        assert_eq!(w.record(at(10), Some("SPY")), None);
+        assert!(w.check(at(20)).is_empty());
+
+        // QQQ never received anything.
+        assert_eq!(w.check(at(31)), [FeedHealth::SymbolStale { symbol: "QQQ".to_string(), last: None }]);
+        assert_eq!(w.check(at(35)), []);
+        assert_eq!(w.check(at(41)), [
+            FeedHealth::SymbolStale { symbol: "SPY".to_string(), last: Some(at(10)) },
+            FeedHealth::ConnectionStale { last: at(10) },
+        ]);
+        assert_eq!(w.record(at(50), Some("SPY")), Some(FeedHealth::SymbolRecovered { symbol: "SPY".to_string() }));
+        assert_eq!(w.record(at(51), Some("IWM")), None);
+        assert!(w.check(at(60)).is_empty());
+    }
+
+    #[test]
+    fn test_market_hours_only() {
+        let night = chrono::NaiveDateTime::parse_from_str("2024-07-03 03:00:00", "%Y-%m-%d %H:%M:%S").unwrap().and_utc();
+        let mut w = FeedWatchdog::new(&["SPY"], Duration::from_secs(30), true, night);
+        assert!(w.check(night + chrono::Duration::hours(1)).is_empty());
+        let mut w = FeedWatchdog::new(&["SPY"], Duration::from_secs(30), false, night);
+        assert_eq!(w.check(night + chrono::Duration::hours(1)).len(), 2);
+    }
+
+    #[test]
+    fn test_payload_symbol() {
+        assert_eq!(payload_symbol(r#"{"type":"trade","symbol":"SPY","price":"1"}"#), Some("SPY"));
+        assert_eq!(payload_symbol(r#"{"type":"heartbeat"}"#), None);
+    }
+}